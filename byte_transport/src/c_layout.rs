@@ -0,0 +1,108 @@
+use core::mem::size_of;
+
+use crate::Error;
+
+/// Byte order of the scalars in a `#[bt(c_layout)]` struct
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    #[cfg(target_endian = "little")]
+    pub const NATIVE: Endian = Endian::Little;
+    #[cfg(target_endian = "big")]
+    pub const NATIVE: Endian = Endian::Big;
+}
+
+/// Types written exactly as they sit in memory under `#[repr(C)]`, padding included.
+/// Every value takes `size_of::<Self>()` bytes on the wire.
+pub trait CLayout: Sized {
+    fn write_c(&self, bytes: &mut Vec<u8>, endian: Endian);
+
+    /// Reads a value from exactly `size_of::<Self>()` bytes
+    fn read_c(bytes: &[u8], endian: Endian) -> Result<Self, Error>;
+}
+
+/// Plain old data: no padding and every bit pattern is a valid value, so the
+/// in-memory bytes can be copied as they are.
+///
+/// # Safety
+/// Implementors must be `#[repr(C)]` (or a primitive) with no padding bytes, and
+/// any `size_of::<Self>()` bytes must be a valid value.
+pub unsafe trait Pod: Copy + 'static {
+    fn as_bytes(&self) -> &[u8] {
+        // Safety: Pod types have no padding, so every byte is initialized
+        unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+
+    /// Copies a value out of exactly `size_of::<Self>()` bytes, which need not be aligned
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != size_of::<Self>() {
+            return Err(Error::UnexpectedEof { needed: size_of::<Self>(), remaining: bytes.len() });
+        }
+        // Safety: the length is checked and every bit pattern is valid for Pod types
+        Ok(unsafe { core::ptr::read_unaligned(bytes.as_ptr() as *const Self) })
+    }
+}
+
+macro_rules! impl_c_layout {
+    ($($scalar:ty),*) => {
+        $(
+            impl CLayout for $scalar {
+                fn write_c(&self, bytes: &mut Vec<u8>, endian: Endian) {
+                    match endian {
+                        Endian::Little => bytes.extend_from_slice(&self.to_le_bytes()),
+                        Endian::Big => bytes.extend_from_slice(&self.to_be_bytes()),
+                    }
+                }
+
+                fn read_c(bytes: &[u8], endian: Endian) -> Result<Self, Error> {
+                    let array = bytes.try_into().map_err(|_| Error::SimpleDecodeTryFrom)?;
+                    match endian {
+                        Endian::Little => Ok(<$scalar>::from_le_bytes(array)),
+                        Endian::Big => Ok(<$scalar>::from_be_bytes(array)),
+                    }
+                }
+            }
+
+            unsafe impl Pod for $scalar {}
+        )*
+    };
+}
+
+impl_c_layout!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl CLayout for bool {
+    fn write_c(&self, bytes: &mut Vec<u8>, _endian: Endian) {
+        bytes.push(*self as u8);
+    }
+
+    fn read_c(bytes: &[u8], _endian: Endian) -> Result<Self, Error> {
+        match bytes {
+            [0u8] => Ok(false),
+            [1u8] => Ok(true),
+            _ => Err(Error::SimpleDecodeError("Error parsing bool".to_string())),
+        }
+    }
+}
+
+impl<T: CLayout, const N: usize> CLayout for [T; N] {
+    fn write_c(&self, bytes: &mut Vec<u8>, endian: Endian) {
+        for element in self {
+            element.write_c(bytes, endian);
+        }
+    }
+
+    fn read_c(bytes: &[u8], endian: Endian) -> Result<Self, Error> {
+        // Zero sized elements have nothing to read
+        let elements = match size_of::<T>() {
+            0 => (0..N).map(|_| T::read_c(&[], endian)).collect::<Result<Vec<T>, Error>>()?,
+            size => bytes.chunks_exact(size).map(|chunk| T::read_c(chunk, endian)).collect::<Result<Vec<T>, Error>>()?,
+        };
+        elements.try_into().map_err(|_| Error::SimpleDecodeTryFrom)
+    }
+}
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
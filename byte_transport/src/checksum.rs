@@ -0,0 +1,64 @@
+use crate::{ByteDecode, ByteEncode};
+
+/// Checksum written by a `#[bt(checksum = ...)]` field over the bytes of the struct before it
+pub trait Checksum {
+    type Output: ByteEncode + ByteDecode + PartialEq + Into<u64>;
+
+    fn compute(bytes: &[u8]) -> Self::Output;
+}
+
+/// CRC-32 (IEEE 802.3), as used by zip, png and ethernet
+pub struct Crc32;
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xedb8_8320,
+                _ => crc >> 1,
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+impl Checksum for Crc32 {
+    type Output = u32;
+
+    fn compute(bytes: &[u8]) -> u32 {
+        !bytes.iter().fold(!0u32, |crc, byte| {
+            CRC32_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8)
+        })
+    }
+}
+
+/// Wrapping sum of every byte
+pub struct Sum8;
+
+impl Checksum for Sum8 {
+    type Output = u8;
+
+    fn compute(bytes: &[u8]) -> u8 {
+        bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+    }
+}
+
+/// Xor of every byte
+pub struct Xor8;
+
+impl Checksum for Xor8 {
+    type Output = u8;
+
+    fn compute(bytes: &[u8]) -> u8 {
+        bytes.iter().fold(0u8, |xor, byte| xor ^ byte)
+    }
+}
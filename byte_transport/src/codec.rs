@@ -0,0 +1,80 @@
+use std::marker::PhantomData;
+
+use bytes::{Buf, BytesMut};
+
+use crate::frame::frame_size;
+use crate::{ByteDecode, ByteEncode, DecoderOptions, Error, FrameWriter, DEFAULT_MAX_FRAME_SIZE, FRAME_HEADER_LEN};
+
+/// `tokio_util` codec reading `In` messages and writing `Out` messages, framed the same way as
+/// `FrameWriter`, so a `Framed<TcpStream, ByteTransportCodec<In, Out>>` yields typed messages
+pub struct ByteTransportCodec<In, Out> {
+    writer: FrameWriter,
+    options: DecoderOptions,
+    // A fn pointer keeps the codec Send and Sync whatever the message types are
+    marker: PhantomData<fn(Out) -> In>,
+}
+
+impl<In, Out> Default for ByteTransportCodec<In, Out> {
+    fn default() -> Self {
+        ByteTransportCodec::with_max_frame_size(DEFAULT_MAX_FRAME_SIZE)
+    }
+}
+
+impl<In, Out> ByteTransportCodec<In, Out> {
+    pub fn new() -> Self {
+        ByteTransportCodec::default()
+    }
+
+    pub fn with_max_frame_size(max_frame_size: usize) -> Self {
+        ByteTransportCodec {
+            writer: FrameWriter::with_max_frame_size(max_frame_size),
+            options: DecoderOptions::default(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Options used for the `Decoder` of every received frame
+    pub fn with_decoder_options(mut self, options: DecoderOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn max_frame_size(&self) -> usize {
+        self.writer.max_frame_size()
+    }
+}
+
+impl<In, Out> Clone for ByteTransportCodec<In, Out> {
+    fn clone(&self) -> Self {
+        ByteTransportCodec { writer: self.writer, options: self.options, marker: PhantomData }
+    }
+}
+
+impl<In: ByteDecode, Out> tokio_util::codec::Decoder for ByteTransportCodec<In, Out> {
+    type Item = In;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<In>, Error> {
+        let Some(header) = src.first_chunk::<FRAME_HEADER_LEN>() else {
+            return Ok(None);
+        };
+        let size = frame_size(*header, self.max_frame_size())?;
+        if src.len() < FRAME_HEADER_LEN + size {
+            // Partial frame, make room for the rest before asking for more
+            src.reserve(FRAME_HEADER_LEN + size - src.len());
+            return Ok(None);
+        }
+        src.advance(FRAME_HEADER_LEN);
+        let payload = src.split_to(size);
+        In::simple_decode(&mut crate::Decoder::with_options(payload.to_vec(), self.options)).map(Some)
+    }
+}
+
+impl<In, Out: ByteEncode> tokio_util::codec::Encoder<Out> for ByteTransportCodec<In, Out> {
+    type Error = Error;
+
+    fn encode(&mut self, item: Out, dst: &mut BytesMut) -> Result<(), Error> {
+        dst.extend_from_slice(&self.writer.frame(&item)?);
+        Ok(())
+    }
+}
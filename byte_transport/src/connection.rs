@@ -0,0 +1,585 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::{ByteDecode, ByteEncode, Decoder, DecoderOptions, Error, Transport, UdpTransport};
+
+/// Bytes in front of every packet: sequence, latest ack, ack bitfield, channel id and message count
+pub const PACKET_HEADER_LEN: usize = 11;
+
+/// Bytes in front of every message in a packet: id, fragment index, fragment count and length
+pub const MESSAGE_HEADER_LEN: usize = 6;
+
+// Sent packets are forgotten once this many newer ones have gone out; any messages
+// they carried are resent on their timer regardless
+const SENT_HISTORY: u16 = 1024;
+
+/// Tuning for `Connection` and `ReliableChannel`, the defaults suit a game on a typical internet link
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReliableConfig {
+    /// Messages are packed into packets up to this size, larger ones are split into fragments that fit
+    pub max_packet_size: usize,
+    /// Round trip time assumed until the first ack arrives
+    pub initial_rtt: Duration,
+    /// Unacked messages are resent after twice the smoothed round trip time, kept within these bounds
+    pub min_resend_delay: Duration,
+    pub max_resend_delay: Duration,
+    /// Most messages queued or waiting for an ack per channel, counting each fragment,
+    /// at most 32768 so ids can't be confused after wrapping
+    pub max_in_flight: usize,
+    /// Most fragments one message may be split into, at most 255. Larger messages fail
+    /// to send, and packets announcing more are rejected.
+    pub max_fragments: usize,
+    /// Bytes of incomplete or held back messages each channel keeps. Past it unreliable
+    /// channels drop their oldest incomplete message, while reliable channels leave new
+    /// fragments unacked for the peer to resend, except those of the message they wait on.
+    pub max_reassembly_bytes: usize,
+    /// Incomplete messages on unreliable channels are dropped when their last fragment
+    /// is this late, as lost fragments are never resent there
+    pub reassembly_timeout: Duration,
+}
+
+impl Default for ReliableConfig {
+    fn default() -> Self {
+        ReliableConfig {
+            max_packet_size: 1200,
+            initial_rtt: Duration::from_millis(100),
+            min_resend_delay: Duration::from_millis(10),
+            max_resend_delay: Duration::from_secs(1),
+            max_in_flight: 1024,
+            max_fragments: 64,
+            max_reassembly_bytes: 1024 * 1024,
+            reassembly_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// How a channel of a `Connection` delivers its messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Every message arrives, in the order it was sent
+    ReliableOrdered,
+    /// Every message arrives exactly once, as soon as it gets there
+    ReliableUnordered,
+    /// Messages are sent once, and one arriving after a newer message is dropped as stale
+    UnreliableSequenced,
+}
+
+impl Delivery {
+    fn is_reliable(self) -> bool {
+        self != Delivery::UnreliableSequenced
+    }
+}
+
+struct Outgoing {
+    id: u16,
+    fragment: Fragment,
+    last_sent: Option<Instant>,
+}
+
+// One piece of a message, the whole of it when `count` is 1. The fragments of a
+// message have consecutive ids, so the first id is the fragment's id less its index.
+struct Fragment {
+    index: u8,
+    count: u8,
+    payload: Vec<u8>,
+}
+
+// A message with some of its fragments received
+struct Partial {
+    fragments: Vec<Option<Vec<u8>>>,
+    missing: usize,
+    bytes: usize,
+    last_fragment: Instant,
+}
+
+struct SentPacket {
+    sent_at: Instant,
+    channel: u8,
+    messages: Vec<u16>,
+}
+
+struct Channel {
+    name: String,
+    delivery: Delivery,
+    next_send_id: u16,
+    // Unacked messages for reliable channels, unsent ones otherwise
+    outgoing: VecDeque<Outgoing>,
+    // Lowest id not received yet, or one past the newest for sequenced channels
+    next_receive_id: u16,
+    // Reliable fragments received past a gap: held back for ordered channels,
+    // remembered as `None` once passed on for unordered ones
+    early: HashMap<u16, Option<Fragment>>,
+    // Incomplete messages by the id of their first fragment
+    partial: HashMap<u16, Partial>,
+    // Payload bytes in `early` and `partial`
+    held_bytes: usize,
+    delivered: VecDeque<Vec<u8>>,
+}
+
+impl Channel {
+    fn new(name: String, delivery: Delivery) -> Self {
+        Channel {
+            name,
+            delivery,
+            next_send_id: 0,
+            outgoing: VecDeque::new(),
+            next_receive_id: 0,
+            early: HashMap::new(),
+            partial: HashMap::new(),
+            held_bytes: 0,
+            delivered: VecDeque::new(),
+        }
+    }
+
+    // Returns false when a reliable fragment was refused for lack of room, in which
+    // case the packet holding it mustn't be acked
+    fn receive(&mut self, id: u16, fragment: Fragment, config: &ReliableConfig, now: Instant) -> bool {
+        let first = id.wrapping_sub(fragment.index as u16);
+        match self.delivery {
+            Delivery::UnreliableSequenced => {
+                // Anything from before the last delivered message is stale
+                if sequence_newer(self.next_receive_id, first) {
+                    return true;
+                }
+                let count = fragment.count as u16;
+                if let Some(message) = self.assemble(first, fragment, now) {
+                    self.delivered.push_back(message);
+                    self.next_receive_id = first.wrapping_add(count);
+                    let next = self.next_receive_id;
+                    self.drop_partials(|first, _| sequence_newer(next, first));
+                }
+                while self.held_bytes > config.max_reassembly_bytes {
+                    let Some(oldest) = self.partial.iter().min_by_key(|(_, partial)| partial.last_fragment).map(|(first, _)| *first) else {
+                        break;
+                    };
+                    self.drop_partials(|first, _| first == oldest);
+                }
+                return true;
+            },
+            // Ids behind the next expected one were seen already
+            _ if sequence_newer(self.next_receive_id, id) || self.early.contains_key(&id) => return true,
+            _ if !self.has_room(first, &fragment, config) => return false,
+            Delivery::ReliableOrdered => {
+                self.held_bytes += fragment.payload.len();
+                self.early.insert(id, Some(fragment));
+            },
+            Delivery::ReliableUnordered => {
+                self.early.insert(id, None);
+                if let Some(message) = self.assemble(first, fragment, now) {
+                    self.delivered.push_back(message);
+                }
+            },
+        }
+        while let Some(held) = self.early.remove(&self.next_receive_id) {
+            let id = self.next_receive_id;
+            self.next_receive_id = id.wrapping_add(1);
+            // Ordered fragments are passed on in id order, so a message completes in order too
+            if let Some(fragment) = held {
+                self.held_bytes -= fragment.payload.len();
+                if let Some(message) = self.assemble(id.wrapping_sub(fragment.index as u16), fragment, now) {
+                    self.delivered.push_back(message);
+                }
+            }
+        }
+        true
+    }
+
+    // Whether a reliable fragment fits in the budget. The message holding up
+    // `next_receive_id` is always let in, so the channel can't get stuck full.
+    fn has_room(&self, first: u16, fragment: &Fragment, config: &ReliableConfig) -> bool {
+        let held = self.delivery == Delivery::ReliableOrdered || fragment.count > 1;
+        let waiting_on = (self.next_receive_id.wrapping_sub(first) as usize) < fragment.count as usize;
+        !held || waiting_on || self.held_bytes + fragment.payload.len() <= config.max_reassembly_bytes
+    }
+
+    // Adds a fragment to its message, returning the message once it is complete
+    fn assemble(&mut self, first: u16, fragment: Fragment, now: Instant) -> Option<Vec<u8>> {
+        if fragment.count == 1 {
+            return Some(fragment.payload);
+        }
+        let count = fragment.count as usize;
+        if self.partial.get(&first).is_some_and(|partial| partial.fragments.len() != count) {
+            // Two messages claiming the same first id can't both be right, keep the newer
+            self.drop_partials(|id, _| id == first);
+        }
+        let partial = self.partial.entry(first).or_insert_with(|| Partial {
+            fragments: (0..count).map(|_| None).collect(),
+            missing: count,
+            bytes: 0,
+            last_fragment: now,
+        });
+        let slot = &mut partial.fragments[fragment.index as usize];
+        if slot.is_some() {
+            return None;
+        }
+        partial.missing -= 1;
+        partial.bytes += fragment.payload.len();
+        partial.last_fragment = now;
+        self.held_bytes += fragment.payload.len();
+        *slot = Some(fragment.payload);
+
+        if partial.missing == 0 {
+            let partial = self.partial.remove(&first)?;
+            self.held_bytes -= partial.bytes;
+            return Some(partial.fragments.into_iter().flatten().flatten().collect());
+        }
+        None
+    }
+
+    fn drop_partials(&mut self, mut condition: impl FnMut(u16, &Partial) -> bool) {
+        let held_bytes = &mut self.held_bytes;
+        self.partial.retain(|first, partial| {
+            let drop = condition(*first, partial);
+            if drop {
+                *held_bytes -= partial.bytes;
+            }
+            !drop
+        });
+    }
+}
+
+// `a` comes after `b`, allowing for both having wrapped
+fn sequence_newer(a: u16, b: u16) -> bool {
+    a != b && a.wrapping_sub(b) < 0x8000
+}
+
+/// Several named channels with their own delivery guarantees over one unreliable
+/// transport, normally UDP. Both peers must add the same channels in the same order,
+/// as channels are numbered by the order they were added.
+///
+/// Every packet carries a wrapping u16 sequence number along with the latest sequence
+/// received from the peer and a bitfield of the 31 before it, so acks ride along with
+/// regular traffic, followed by the id of the channel whose messages it holds. Reliable
+/// messages stay queued until a packet holding them is acked and are resent on a timer
+/// derived from the measured round trip time. Each channel numbers its own messages, so
+/// a gap on one never holds up another.
+///
+/// Messages too big for one packet are split into fragments numbered like messages of
+/// their own, so on reliable channels each lost fragment is resent by itself, and are
+/// put back together by the receiver before delivery.
+///
+/// Nothing happens on the network outside `update`, which should be called every tick.
+pub struct Connection<T = UdpTransport> {
+    transport: T,
+    config: ReliableConfig,
+    decoder_options: DecoderOptions,
+    rtt: Duration,
+    channels: Vec<Channel>,
+
+    local_sequence: u16,
+    remote_sequence: u16,
+    // Bit `i` is set when `remote_sequence - i` has been received
+    received_bits: u32,
+    ack_pending: bool,
+    sent: HashMap<u16, SentPacket>,
+    dropped_packets: usize,
+    last_packet_error: Option<Error>,
+}
+
+impl<T: Transport> Connection<T> {
+    pub fn new(transport: T) -> Self {
+        Connection::with_config(transport, ReliableConfig::default())
+    }
+
+    pub fn with_config(transport: T, mut config: ReliableConfig) -> Self {
+        config.max_in_flight = config.max_in_flight.min(0x8000);
+        config.max_fragments = config.max_fragments.clamp(1, u8::MAX as usize);
+        Connection {
+            transport,
+            rtt: config.initial_rtt,
+            config,
+            decoder_options: DecoderOptions::default(),
+            channels: Vec::new(),
+            local_sequence: 0,
+            remote_sequence: 0,
+            received_bits: 0,
+            ack_pending: false,
+            sent: HashMap::new(),
+            dropped_packets: 0,
+            last_packet_error: None,
+        }
+    }
+
+    /// Options used for the `Decoder` of every message taken with `recv`
+    pub fn with_decoder_options(mut self, options: DecoderOptions) -> Self {
+        self.decoder_options = options;
+        self
+    }
+
+    /// Adds a channel, returning the id it goes by on the wire. A connection has at most 256.
+    pub fn add_channel(&mut self, name: impl Into<String>, delivery: Delivery) -> Result<u8, Error> {
+        let name = name.into();
+        if self.channel_id(&name).is_some() {
+            return Err(Error::DuplicateChannel(name));
+        }
+        let id = u8::try_from(self.channels.len()).map_err(|_| Error::TooManyChannels { max: u8::MAX as usize + 1 })?;
+        self.channels.push(Channel::new(name, delivery));
+        Ok(id)
+    }
+
+    /// Builder form of `add_channel`
+    pub fn with_channel(mut self, name: impl Into<String>, delivery: Delivery) -> Result<Self, Error> {
+        self.add_channel(name, delivery)?;
+        Ok(self)
+    }
+
+    pub fn channel_id(&self, name: &str) -> Option<u8> {
+        self.channels.iter().position(|channel| channel.name == name).map(|id| id as u8)
+    }
+
+    fn named(&self, name: &str) -> Result<u8, Error> {
+        self.channel_id(name).ok_or_else(|| Error::UnknownChannel(name.to_string()))
+    }
+
+    /// Queues a message on the named channel, it goes out on the next `update`
+    pub fn send<M: ByteEncode>(&mut self, channel: &str, message: &M) -> Result<(), Error> {
+        let mut payload = Vec::new();
+        message.simple_encode(&mut payload)?;
+        self.send_bytes(channel, payload)
+    }
+
+    pub fn send_bytes(&mut self, channel: &str, payload: Vec<u8>) -> Result<(), Error> {
+        let id = self.named(channel)?;
+        self.send_on(id, payload)
+    }
+
+    /// Next message delivered on the named channel, if any
+    pub fn recv<M: ByteDecode>(&mut self, channel: &str) -> Result<Option<M>, Error> {
+        let options = self.decoder_options;
+        self.recv_bytes(channel)?.map(|payload| M::simple_decode(&mut Decoder::with_options(payload, options))).transpose()
+    }
+
+    pub fn recv_bytes(&mut self, channel: &str) -> Result<Option<Vec<u8>>, Error> {
+        let id = self.named(channel)?;
+        Ok(self.recv_on(id))
+    }
+
+    /// Largest payload sent as a single message, bigger ones are split into fragments of this size
+    pub fn fragment_size(&self) -> usize {
+        self.config.max_packet_size.saturating_sub(PACKET_HEADER_LEN + MESSAGE_HEADER_LEN).clamp(1, u16::MAX as usize)
+    }
+
+    pub(crate) fn send_on(&mut self, channel: u8, payload: Vec<u8>) -> Result<(), Error> {
+        let fragment_size = self.fragment_size();
+        let count = payload.len().div_ceil(fragment_size).max(1);
+        if count > self.config.max_fragments {
+            return Err(Error::FrameTooLarge { size: payload.len(), max: self.config.max_fragments * fragment_size });
+        }
+        let max_in_flight = self.config.max_in_flight;
+        let channel = &mut self.channels[channel as usize];
+        if channel.outgoing.len() + count > max_in_flight {
+            return Err(Error::SendWindowFull { capacity: max_in_flight });
+        }
+        if count == 1 {
+            channel.outgoing.push_back(Outgoing { id: channel.next_send_id, fragment: Fragment { index: 0, count: 1, payload }, last_sent: None });
+            channel.next_send_id = channel.next_send_id.wrapping_add(1);
+            return Ok(());
+        }
+        for (index, chunk) in payload.chunks(fragment_size).enumerate() {
+            let fragment = Fragment { index: index as u8, count: count as u8, payload: chunk.to_vec() };
+            channel.outgoing.push_back(Outgoing { id: channel.next_send_id, fragment, last_sent: None });
+            channel.next_send_id = channel.next_send_id.wrapping_add(1);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn recv_on(&mut self, channel: u8) -> Option<Vec<u8>> {
+        self.channels[channel as usize].delivered.pop_front()
+    }
+
+    /// Reads every packet waiting on the transport, then sends new messages, resends
+    /// those whose ack is overdue and acks what was received. Packets that can't be
+    /// decoded are dropped and counted in `dropped_packets`, only transport errors fail.
+    pub fn update(&mut self) -> Result<(), Error> {
+        let now = Instant::now();
+        while let Some(packet) = self.transport.try_recv_bytes()? {
+            // Anyone can send a datagram, so a bad one mustn't hold up the rest
+            if let Err(error) = self.receive_packet(packet, now) {
+                self.dropped_packets += 1;
+                self.last_packet_error = Some(error);
+            }
+        }
+        let timeout = self.config.reassembly_timeout;
+        for channel in self.channels.iter_mut().filter(|channel| !channel.delivery.is_reliable()) {
+            channel.drop_partials(|_, partial| now.duration_since(partial.last_fragment) >= timeout);
+        }
+        self.flush(now)
+    }
+
+    /// Smoothed round trip time measured from acks
+    pub fn rtt(&self) -> Duration {
+        self.rtt
+    }
+
+    /// Messages on every channel that are queued or that the peer has not acked yet
+    pub fn in_flight(&self) -> usize {
+        self.channels.iter().map(|channel| channel.outgoing.len()).sum()
+    }
+
+    pub fn config(&self) -> &ReliableConfig {
+        &self.config
+    }
+
+    pub fn decoder_options(&self) -> DecoderOptions {
+        self.decoder_options
+    }
+
+    /// Packets dropped by `update` because they couldn't be decoded
+    pub fn dropped_packets(&self) -> usize {
+        self.dropped_packets
+    }
+
+    /// Why the most recently dropped packet was rejected
+    pub fn last_packet_error(&self) -> Option<&Error> {
+        self.last_packet_error.as_ref()
+    }
+
+    /// Bytes held for messages still missing fragments or waiting on earlier ones, across every channel
+    pub fn reassembly_bytes(&self) -> usize {
+        self.channels.iter().map(|channel| channel.held_bytes).sum()
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+
+    fn receive_packet(&mut self, packet: Vec<u8>, now: Instant) -> Result<(), Error> {
+        let mut decoder = Decoder::new(packet);
+        let sequence = u16::simple_decode(&mut decoder)?;
+        let ack = u16::simple_decode(&mut decoder)?;
+        let ack_bits = u32::simple_decode(&mut decoder)?;
+        let channel = u8::simple_decode(&mut decoder)?;
+        let count = u16::simple_decode(&mut decoder)?;
+        // Parse everything up front so a bad packet changes nothing
+        let mut messages = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let id = u16::simple_decode(&mut decoder)?;
+            let index = u8::simple_decode(&mut decoder)?;
+            let count = u8::simple_decode(&mut decoder)?;
+            let len = u16::simple_decode(&mut decoder)? as usize;
+            // Refuse to set aside room for more fragments than we'd ever send
+            if count as usize > self.config.max_fragments.min(u8::MAX as usize) {
+                return Err(Error::TooManyFragments { count: count as usize, max: self.config.max_fragments });
+            }
+            if index >= count {
+                return Err(Error::SimpleDecodeError(format!("Fragment {index} of a message with {count} fragments")));
+            }
+            if decoder.remaining() < len {
+                return Err(Error::UnexpectedEof { needed: len, remaining: decoder.remaining() });
+            }
+            let payload = decoder.bytes[decoder.index..decoder.index + len].to_vec();
+            decoder.index += len;
+            messages.push((id, Fragment { index, count, payload }));
+        }
+        if !messages.is_empty() && channel as usize >= self.channels.len() {
+            return Err(Error::UnknownChannelId(channel));
+        }
+
+        self.process_acks(ack, ack_bits, now);
+        let mut accepted = true;
+        let channel = self.channels.get_mut(channel as usize);
+        if let Some(channel) = channel.filter(|_| !messages.is_empty()) {
+            for (id, fragment) in messages {
+                accepted &= channel.receive(id, fragment, &self.config, now);
+            }
+            self.ack_pending |= accepted && channel.delivery.is_reliable();
+        }
+        // Left unacked, the peer resends every message in the packet once there's room
+        if accepted {
+            self.record_received(sequence);
+        }
+        Ok(())
+    }
+
+    fn record_received(&mut self, sequence: u16) {
+        if self.received_bits == 0 || sequence_newer(sequence, self.remote_sequence) {
+            let shift = sequence.wrapping_sub(self.remote_sequence) as u32;
+            self.received_bits = self.received_bits.checked_shl(shift).unwrap_or(0) | 1;
+            self.remote_sequence = sequence;
+        } else {
+            let back = self.remote_sequence.wrapping_sub(sequence) as u32;
+            self.received_bits |= 1u32.checked_shl(back).unwrap_or(0);
+        }
+    }
+
+    fn process_acks(&mut self, ack: u16, ack_bits: u32, now: Instant) {
+        for back in (0..32).filter(|back| ack_bits & (1 << back) != 0) {
+            let Some(packet) = self.sent.remove(&ack.wrapping_sub(back)) else {
+                continue;
+            };
+            self.rtt = (self.rtt * 7 + now.duration_since(packet.sent_at)) / 8;
+            self.channels[packet.channel as usize].outgoing.retain(|message| !packet.messages.contains(&message.id));
+        }
+    }
+
+    fn flush(&mut self, now: Instant) -> Result<(), Error> {
+        let resend_delay = (self.rtt * 2).clamp(self.config.min_resend_delay, self.config.max_resend_delay);
+        let mut packets = Vec::new();
+        for (index, channel) in self.channels.iter_mut().enumerate() {
+            let reliable = channel.delivery.is_reliable();
+            let mut ids = Vec::new();
+            let mut body = Vec::new();
+            for message in &mut channel.outgoing {
+                if message.last_sent.is_some_and(|last_sent| now.duration_since(last_sent) < resend_delay) {
+                    continue;
+                }
+                let fragment = &message.fragment;
+                let size = MESSAGE_HEADER_LEN + fragment.payload.len();
+                if !ids.is_empty() && PACKET_HEADER_LEN + body.len() + size > self.config.max_packet_size {
+                    packets.push((index as u8, reliable, std::mem::take(&mut ids), std::mem::take(&mut body)));
+                }
+                ids.push(message.id);
+                body.extend_from_slice(&message.id.to_le_bytes());
+                body.extend_from_slice(&[fragment.index, fragment.count]);
+                body.extend_from_slice(&(fragment.payload.len() as u16).to_le_bytes());
+                body.extend_from_slice(&fragment.payload);
+                message.last_sent = Some(now);
+            }
+            if !ids.is_empty() {
+                packets.push((index as u8, reliable, ids, body));
+            }
+            // Unreliable messages go out once
+            if !reliable {
+                channel.outgoing.clear();
+            }
+        }
+        if packets.is_empty() && self.ack_pending {
+            packets.push((0, false, Vec::new(), Vec::new()));
+        }
+
+        for (channel, reliable, ids, body) in packets {
+            self.send_packet(channel, reliable, ids, &body, now)?;
+        }
+        Ok(())
+    }
+
+    fn send_packet(&mut self, channel: u8, reliable: bool, ids: Vec<u16>, body: &[u8], now: Instant) -> Result<(), Error> {
+        let sequence = self.local_sequence;
+        self.local_sequence = sequence.wrapping_add(1);
+
+        let mut packet = Vec::with_capacity(PACKET_HEADER_LEN + body.len());
+        sequence.simple_encode(&mut packet)?;
+        self.remote_sequence.simple_encode(&mut packet)?;
+        self.received_bits.simple_encode(&mut packet)?;
+        channel.simple_encode(&mut packet)?;
+        (ids.len() as u16).simple_encode(&mut packet)?;
+        packet.extend_from_slice(body);
+        self.transport.send_bytes(&packet)?;
+        self.ack_pending = false;
+
+        self.sent.remove(&sequence.wrapping_sub(SENT_HISTORY));
+        // Only reliable messages are resent, so only their packets need tracking
+        if reliable {
+            self.sent.insert(sequence, SentPacket { sent_at: now, channel, messages: ids });
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,55 @@
 #[derive(Debug)]
 pub enum Error {
-    DecodingEnumVariant(u8),
+    DecodingEnumVariant(u64),
     SimpleDecodeError(String),
     SimpleDecodeTryFrom,
     SimpleEncodeError,
+    /// The input ended part way through a value
+    UnexpectedEof { needed: usize, remaining: usize },
+    /// A decoded value was rejected by a range constraint or validation hook
+    Validation { field: String, reason: String },
+    /// A NaN or infinite float was decoded while `DecoderOptions::finite_floats` was set
+    NonFiniteFloat,
+    /// A `#[bt(try_from)]` proxy could not be converted into the target type
+    Conversion { type_name: &'static str, reason: String },
+    /// A `#[bt(magic)]` field did not hold the expected constant
+    MagicMismatch { field: String, expected: Vec<u8>, found: Vec<u8> },
+    /// A `#[bt(checksum)]` field did not match the checksum of the bytes before it
+    ChecksumMismatch { field: String, stored: u64, computed: u64 },
+    /// A frame's payload is over the configured maximum frame size
+    FrameTooLarge { size: usize, max: usize },
+    /// No message type is registered for this id
+    UnknownMessageId(u16),
+    /// A message id or type was registered twice, holding the id already in use
+    DuplicateMessageId(u16),
+    /// A message type was encoded through a registry it was never registered with
+    UnregisteredMessage { type_name: &'static str },
+    /// The remote handler of an RPC call failed, with its error as text
+    Remote(String),
+    /// No RPC response arrived before the call's deadline
+    Timeout,
+    /// The RPC call was cancelled before its response arrived
+    Cancelled,
+    /// An RPC server already has a handler for this method id
+    DuplicateMethod(u16),
+    /// Too many reliable messages are waiting for an ack to queue another
+    SendWindowFull { capacity: usize },
+    /// No channel of the connection has this name
+    UnknownChannel(String),
+    /// A channel name was added to a connection twice
+    DuplicateChannel(String),
+    /// A connection already has as many channels as a packet's channel id can number
+    TooManyChannels { max: usize },
+    /// A packet arrived for a channel id the connection doesn't have
+    UnknownChannelId(u8),
+    /// A packet announced a message split into more fragments than allowed
+    TooManyFragments { count: usize, max: usize },
+    /// Reading or writing the underlying stream failed
+    Io(std::io::Error),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
 }
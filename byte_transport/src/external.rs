@@ -0,0 +1,44 @@
+use crate::{Decoder, Error, VarInt};
+
+/// Enum encoding with the tag left out, for `#[bt(tag_from)]` fields whose tag is another field
+pub trait EncodeVariant {
+    /// Tag that selects this value's variant
+    fn variant_tag(&self) -> u64;
+
+    /// Writes the variant's fields without its tag
+    fn encode_variant(&self, bytes: &mut Vec<u8>) -> Result<(), Error>;
+}
+
+/// Enum decoding with the tag supplied by the caller instead of read from the input
+pub trait DecodeVariant: Sized {
+    fn decode_variant(tag: u64, decoder: &mut Decoder) -> Result<Self, Error>;
+}
+
+/// Integer fields that `#[bt(count)]` and `#[bt(tag_from)]` can refer to
+pub trait FieldInteger {
+    fn to_u64(&self) -> Result<u64, Error>;
+
+    fn to_count(&self) -> Result<usize, Error> {
+        usize::try_from(self.to_u64()?).map_err(|_| Error::SimpleDecodeTryFrom)
+    }
+}
+
+macro_rules! impl_field_integer {
+    ($($integer:ty),*) => {
+        $(
+            impl FieldInteger for $integer {
+                fn to_u64(&self) -> Result<u64, Error> {
+                    u64::try_from(*self).map_err(|_| Error::SimpleDecodeTryFrom)
+                }
+            }
+        )*
+    };
+}
+
+impl_field_integer!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+impl FieldInteger for VarInt {
+    fn to_u64(&self) -> Result<u64, Error> {
+        Ok(self.0)
+    }
+}
@@ -0,0 +1,140 @@
+use crate::{Decoder, Error};
+
+#[cfg(feature = "bevy")]
+use bevy::prelude::*;
+
+/// Bit pattern every f32 NaN is rewritten to by `CanonicalNan`
+pub const CANONICAL_NAN_F32_BITS: u32 = 0x7fc0_0000;
+/// Bit pattern every f64 NaN is rewritten to by `CanonicalNan`
+pub const CANONICAL_NAN_F64_BITS: u64 = 0x7ff8_0000_0000_0000;
+
+/// Replaces every NaN with a single quiet NaN, so equal values always encode to the same bytes
+pub trait CanonicalNan {
+    fn canonical_nan(&self) -> Self;
+}
+
+impl CanonicalNan for f32 {
+    fn canonical_nan(&self) -> Self {
+        match self.is_nan() {
+            true => f32::from_bits(CANONICAL_NAN_F32_BITS),
+            false => *self,
+        }
+    }
+}
+
+impl CanonicalNan for f64 {
+    fn canonical_nan(&self) -> Self {
+        match self.is_nan() {
+            true => f64::from_bits(CANONICAL_NAN_F64_BITS),
+            false => *self,
+        }
+    }
+}
+
+impl<T: CanonicalNan, const N: usize> CanonicalNan for [T; N] {
+    fn canonical_nan(&self) -> Self {
+        std::array::from_fn(|index| self[index].canonical_nan())
+    }
+}
+
+impl<T: CanonicalNan> CanonicalNan for Vec<T> {
+    fn canonical_nan(&self) -> Self {
+        self.iter().map(CanonicalNan::canonical_nan).collect()
+    }
+}
+
+impl<T: CanonicalNan> CanonicalNan for Option<T> {
+    fn canonical_nan(&self) -> Self {
+        self.as_ref().map(CanonicalNan::canonical_nan)
+    }
+}
+
+#[cfg(feature = "bevy")]
+impl CanonicalNan for Vec3 {
+    fn canonical_nan(&self) -> Self {
+        Vec3::from_array(self.to_array().canonical_nan())
+    }
+}
+
+#[cfg(feature = "bevy")]
+impl CanonicalNan for Quat {
+    fn canonical_nan(&self) -> Self {
+        Quat::from_array(self.to_array().canonical_nan())
+    }
+}
+
+#[cfg(feature = "bevy")]
+impl CanonicalNan for Transform {
+    fn canonical_nan(&self) -> Self {
+        Transform {
+            translation: self.translation.canonical_nan(),
+            rotation: self.rotation.canonical_nan(),
+            scale: self.scale.canonical_nan(),
+        }
+    }
+}
+
+#[cfg(feature = "godot_flag")]
+impl CanonicalNan for godot::builtin::Vector3 {
+    fn canonical_nan(&self) -> Self {
+        Self {
+            x: self.x.canonical_nan(),
+            y: self.y.canonical_nan(),
+            z: self.z.canonical_nan(),
+        }
+    }
+}
+
+#[cfg(feature = "godot_flag")]
+impl CanonicalNan for godot::builtin::Vector2 {
+    fn canonical_nan(&self) -> Self {
+        Self {
+            x: self.x.canonical_nan(),
+            y: self.y.canonical_nan(),
+        }
+    }
+}
+
+#[cfg(feature = "godot_flag")]
+impl CanonicalNan for godot::builtin::Quaternion {
+    fn canonical_nan(&self) -> Self {
+        Self {
+            x: self.x.canonical_nan(),
+            y: self.y.canonical_nan(),
+            z: self.z.canonical_nan(),
+            w: self.w.canonical_nan(),
+        }
+    }
+}
+
+#[cfg(feature = "macroquad")]
+impl CanonicalNan for macroquad::prelude::Vec2 {
+    fn canonical_nan(&self) -> Self {
+        Self {
+            x: self.x.canonical_nan(),
+            y: self.y.canonical_nan(),
+        }
+    }
+}
+
+#[cfg(feature = "macroquad")]
+impl CanonicalNan for macroquad::color::Color {
+    fn canonical_nan(&self) -> Self {
+        Self {
+            r: self.r.canonical_nan(),
+            g: self.g.canonical_nan(),
+            b: self.b.canonical_nan(),
+            a: self.a.canonical_nan(),
+        }
+    }
+}
+
+impl Decoder {
+    /// Fails with `Error::NonFiniteFloat` when the decoder only accepts finite floats
+    pub(crate) fn check_float(&self, is_finite: bool) -> Result<(), Error> {
+        match self.options.finite_floats && !is_finite {
+            true => Err(Error::NonFiniteFloat),
+            false => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{ByteDecode, ByteEncode, Decoder, DecoderOptions, Error};
+
+/// Bytes taken by the `u32` little endian length in front of every frame
+pub const FRAME_HEADER_LEN: usize = 4;
+
+/// Largest payload accepted unless configured otherwise, 16 MiB
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+// Payload size announced by a frame header, rejecting oversize frames before anything is
+// allocated. Every reader goes through this so they all agree on the header format.
+pub(crate) fn frame_size(header: [u8; FRAME_HEADER_LEN], max_frame_size: usize) -> Result<usize, Error> {
+    let size = u32::from_le_bytes(header) as usize;
+    match size > max_frame_size {
+        true => Err(Error::FrameTooLarge { size, max: max_frame_size }),
+        false => Ok(size),
+    }
+}
+
+/// Writes messages as `[u32 len][payload]` frames
+#[derive(Debug, Clone, Copy)]
+pub struct FrameWriter {
+    max_frame_size: usize,
+}
+
+impl Default for FrameWriter {
+    fn default() -> Self {
+        FrameWriter { max_frame_size: DEFAULT_MAX_FRAME_SIZE }
+    }
+}
+
+impl FrameWriter {
+    pub fn new() -> Self {
+        FrameWriter::default()
+    }
+
+    pub fn with_max_frame_size(max_frame_size: usize) -> Self {
+        FrameWriter { max_frame_size: max_frame_size.min(u32::MAX as usize) }
+    }
+
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    /// Appends one frame holding `message`, leaving `bytes` untouched if it is too large
+    pub fn write<T: ByteEncode>(&self, message: &T, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        let start = bytes.len();
+        bytes.extend_from_slice(&[0u8; FRAME_HEADER_LEN]);
+        if let Err(error) = message.simple_encode(bytes) {
+            bytes.truncate(start);
+            return Err(error);
+        }
+        let size = bytes.len() - start - FRAME_HEADER_LEN;
+        if size > self.max_frame_size {
+            bytes.truncate(start);
+            return Err(Error::FrameTooLarge { size, max: self.max_frame_size });
+        }
+        bytes[start..start + FRAME_HEADER_LEN].copy_from_slice(&(size as u32).to_le_bytes());
+        Ok(())
+    }
+
+    /// Appends one frame holding already encoded bytes, such as a `MessageRegistry` message
+    pub fn write_payload(&self, payload: &[u8], bytes: &mut Vec<u8>) -> Result<(), Error> {
+        if payload.len() > self.max_frame_size {
+            return Err(Error::FrameTooLarge { size: payload.len(), max: self.max_frame_size });
+        }
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(payload);
+        Ok(())
+    }
+
+    /// Encodes `message` into a new buffer holding a single frame
+    pub fn frame<T: ByteEncode>(&self, message: &T) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::new();
+        self.write(message, &mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+/// Splits a byte stream back into the frames written by `FrameWriter`.
+///
+/// Bytes are pushed as they arrive, in chunks of any size, and complete frames are
+/// taken out one at a time. A frame over the size limit is reported once with
+/// `Error::FrameTooLarge` and its payload is skipped, so the frames after it still decode.
+#[derive(Debug, Clone)]
+pub struct FrameReader {
+    buffer: Vec<u8>,
+    max_frame_size: usize,
+    options: DecoderOptions,
+    // Payload bytes of an oversize frame still to be thrown away
+    discarding: usize,
+}
+
+impl Default for FrameReader {
+    fn default() -> Self {
+        FrameReader::with_max_frame_size(DEFAULT_MAX_FRAME_SIZE)
+    }
+}
+
+impl FrameReader {
+    pub fn new() -> Self {
+        FrameReader::default()
+    }
+
+    pub fn with_max_frame_size(max_frame_size: usize) -> Self {
+        FrameReader { buffer: Vec::new(), max_frame_size, options: DecoderOptions::default(), discarding: 0 }
+    }
+
+    /// Options used for the `Decoder` of every frame read with `read`
+    pub fn with_decoder_options(mut self, options: DecoderOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    /// Bytes received but not yet returned as part of a frame
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Adds received bytes to the end of the stream
+    pub fn push(&mut self, bytes: &[u8]) {
+        let skipped = self.discarding.min(bytes.len());
+        self.discarding -= skipped;
+        self.buffer.extend_from_slice(&bytes[skipped..]);
+    }
+
+    /// Takes the next complete frame's payload, or `None` until more bytes are pushed
+    pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        let Some(header) = self.buffer.first_chunk::<FRAME_HEADER_LEN>() else {
+            return Ok(None);
+        };
+        let size = match frame_size(*header, self.max_frame_size) {
+            Ok(size) => size,
+            Err(Error::FrameTooLarge { size, max }) => {
+                // Drop the header and as much of the payload as has arrived, the rest is skipped in `push`
+                let available = (self.buffer.len() - FRAME_HEADER_LEN).min(size);
+                self.buffer.drain(..FRAME_HEADER_LEN + available);
+                self.discarding = size - available;
+                return Err(Error::FrameTooLarge { size, max });
+            },
+            Err(error) => return Err(error),
+        };
+        if self.buffer.len() < FRAME_HEADER_LEN + size {
+            return Ok(None);
+        }
+        let frame = self.buffer[FRAME_HEADER_LEN..FRAME_HEADER_LEN + size].to_vec();
+        self.buffer.drain(..FRAME_HEADER_LEN + size);
+        Ok(Some(frame))
+    }
+
+    /// Decodes the next complete frame as a `T`, or `None` until more bytes are pushed
+    pub fn read<T: ByteDecode>(&mut self) -> Result<Option<T>, Error> {
+        match self.next_frame()? {
+            Some(frame) => T::simple_decode(&mut Decoder::with_options(frame, self.options)).map(Some),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::io::{Read, Write};
+
+use crate::frame::frame_size;
+use crate::{ByteDecode, ByteEncode, Decoder, DecoderOptions, Error, FrameWriter, DEFAULT_MAX_FRAME_SIZE, FRAME_HEADER_LEN};
+
+/// Writes typed messages to any `std::io::Write` as `FrameWriter` frames
+pub trait WriteMessageExt: Write {
+    fn write_message<T: ByteEncode>(&mut self, message: &T) -> Result<(), Error> {
+        self.write_all(&FrameWriter::new().frame(message)?)?;
+        Ok(())
+    }
+}
+
+impl<W: Write + ?Sized> WriteMessageExt for W {}
+
+/// Reads typed messages written by `WriteMessageExt` or `FrameWriter` from any `std::io::Read`
+pub trait ReadMessageExt: Read {
+    fn read_message<T: ByteDecode>(&mut self) -> Result<T, Error> {
+        self.read_message_with_max::<T>(DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    /// Like `read_message`, failing with `Error::FrameTooLarge` for payloads over `max_frame_size`
+    fn read_message_with_max<T: ByteDecode>(&mut self, max_frame_size: usize) -> Result<T, Error> {
+        self.read_message_with_options::<T>(max_frame_size, DecoderOptions::default())
+    }
+
+    /// Like `read_message_with_max`, decoding the payload with `options`
+    fn read_message_with_options<T: ByteDecode>(&mut self, max_frame_size: usize, options: DecoderOptions) -> Result<T, Error> {
+        let mut header = [0u8; FRAME_HEADER_LEN];
+        self.read_exact(&mut header)?;
+        let mut payload = vec![0u8; frame_size(header, max_frame_size)?];
+        self.read_exact(&mut payload)?;
+        T::simple_decode(&mut Decoder::with_options(payload, options))
+    }
+}
+
+impl<R: Read + ?Sized> ReadMessageExt for R {}
+
+#[cfg(feature = "tokio")]
+pub use self::asynchronous::{AsyncReadMessageExt, AsyncWriteMessageExt};
+
+#[cfg(feature = "tokio")]
+mod asynchronous {
+    use std::future::Future;
+
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+    use crate::frame::frame_size;
+    use crate::{ByteDecode, ByteEncode, Decoder, DecoderOptions, Error, FrameWriter, DEFAULT_MAX_FRAME_SIZE, FRAME_HEADER_LEN};
+
+    /// Writes typed messages to any tokio `AsyncWrite` as `FrameWriter` frames
+    pub trait AsyncWriteMessageExt: AsyncWrite + Unpin + Send {
+        /// The message is encoded up front, so it is not borrowed by the returned future
+        fn write_message<T: ByteEncode>(&mut self, message: &T) -> impl Future<Output = Result<(), Error>> + Send + '_ {
+            let frame = FrameWriter::new().frame(message);
+            async move {
+                self.write_all(&frame?).await?;
+                Ok(())
+            }
+        }
+    }
+
+    impl<W: AsyncWrite + Unpin + Send + ?Sized> AsyncWriteMessageExt for W {}
+
+    /// Reads typed messages written by `AsyncWriteMessageExt` or `FrameWriter` from any tokio `AsyncRead`
+    pub trait AsyncReadMessageExt: AsyncRead + Unpin + Send {
+        fn read_message<T: ByteDecode>(&mut self) -> impl Future<Output = Result<T, Error>> + Send + '_ {
+            self.read_message_with_max::<T>(DEFAULT_MAX_FRAME_SIZE)
+        }
+
+        /// Like `read_message`, failing with `Error::FrameTooLarge` for payloads over `max_frame_size`
+        fn read_message_with_max<T: ByteDecode>(&mut self, max_frame_size: usize) -> impl Future<Output = Result<T, Error>> + Send + '_ {
+            self.read_message_with_options::<T>(max_frame_size, DecoderOptions::default())
+        }
+
+        /// Like `read_message_with_max`, decoding the payload with `options`
+        fn read_message_with_options<T: ByteDecode>(&mut self, max_frame_size: usize, options: DecoderOptions) -> impl Future<Output = Result<T, Error>> + Send + '_ {
+            async move {
+                let mut header = [0u8; FRAME_HEADER_LEN];
+                self.read_exact(&mut header).await?;
+                let mut payload = vec![0u8; frame_size(header, max_frame_size)?];
+                self.read_exact(&mut payload).await?;
+                T::simple_decode(&mut Decoder::with_options(payload, options))
+            }
+        }
+    }
+
+    impl<R: AsyncRead + Unpin + Send + ?Sized> AsyncReadMessageExt for R {}
+}
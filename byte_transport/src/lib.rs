@@ -1,22 +1,88 @@
-use std::{array::TryFromSliceError, time::Duration};
+use std::time::Duration;
 use core::fmt::Debug;
 
+mod c_layout;
+mod checksum;
+#[cfg(feature = "tokio")]
+mod codec;
+mod connection;
 mod error;
-pub use byte_transport_macros::{ByteEncode, ByteDecode};
+mod external;
+mod float;
+mod frame;
+mod io;
+mod registry;
+mod reliable;
+mod rpc;
+mod transport;
+mod varint;
+pub use byte_transport_macros::{protocol, ByteEncode, ByteDecode};
 
 #[cfg(feature = "bevy")]
 use bevy::prelude::*;
 
+pub use c_layout::{CLayout, Endian, Pod};
+pub use checksum::{Checksum, Crc32, Sum8, Xor8};
+#[cfg(feature = "tokio")]
+pub use codec::ByteTransportCodec;
+pub use connection::{Connection, Delivery, ReliableConfig, MESSAGE_HEADER_LEN, PACKET_HEADER_LEN};
 pub use error::Error;
+pub use external::{DecodeVariant, EncodeVariant, FieldInteger};
+pub use float::{CanonicalNan, CANONICAL_NAN_F32_BITS, CANONICAL_NAN_F64_BITS};
+pub use frame::{FrameReader, FrameWriter, DEFAULT_MAX_FRAME_SIZE, FRAME_HEADER_LEN};
+pub use io::{ReadMessageExt, WriteMessageExt};
+#[cfg(feature = "tokio")]
+pub use io::{AsyncReadMessageExt, AsyncWriteMessageExt};
+pub use registry::{Message, MessageRegistry};
+pub use reliable::ReliableChannel;
+pub use rpc::{is_request, PendingCall, Request, RpcClient, RpcServer};
+pub use transport::{MemoryTransport, TcpTransport, Transport, UdpTransport, MAX_DATAGRAM_SIZE};
+pub use varint::VarInt;
 
 pub struct Decoder {
     pub index: usize,
     pub bytes: Vec<u8>,
+    pub options: DecoderOptions,
+}
 
+/// Stricter checks applied while decoding, all off by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecoderOptions {
+    /// Reject NaN and infinite `f32`/`f64` values, including those inside engine vector types
+    pub finite_floats: bool,
 }
 
 impl Decoder {
-    pub fn new(bytes: Vec<u8>) -> Self { Decoder { index: 0, bytes } }
+    pub fn new(bytes: Vec<u8>) -> Self { Decoder { index: 0, bytes, options: DecoderOptions::default() } }
+
+    pub fn with_options(bytes: Vec<u8>, options: DecoderOptions) -> Self {
+        Decoder { index: 0, bytes, options }
+    }
+
+    /// Number of bytes left to decode
+    pub fn remaining(&self) -> usize {
+        self.bytes.len().saturating_sub(self.index)
+    }
+
+    /// True once every byte has been consumed, i.e. a clean end of input
+    pub fn is_at_end(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Consumes the next `len` bytes, failing with `Error::UnexpectedEof` if the input is truncated
+    pub fn read_slice(&mut self, len: usize) -> Result<&[u8], Error> {
+        let remaining = self.remaining();
+        if len > remaining {
+            return Err(Error::UnexpectedEof { needed: len, remaining });
+        }
+        let start = self.index;
+        self.index += len;
+        Ok(&self.bytes[start..self.index])
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        self.read_slice(N)?.try_into().map_err(|_| Error::SimpleDecodeTryFrom)
+    }
 }
 
 pub trait ByteEncode {
@@ -102,7 +168,6 @@ impl ByteEncode for u8 {
 impl<T: ByteEncode> ByteEncode for Vec<T> {
     fn simple_encode(&self, bytes:&mut Vec<u8>) -> Result<(), Error> {
         let vec_length: u16 = self.len() as u16;
-        println!("Encoded Vec Length: {}", vec_length);
         vec_length.simple_encode(bytes)?;
         for encodable in self {
             encodable.simple_encode(bytes)?;
@@ -116,8 +181,8 @@ where
     T: ByteEncode,
 {   
     fn simple_encode(&self, bytes:&mut Vec<u8>) -> Result<(), Error> {
-        for index in 0..N {
-            self[index].simple_encode(bytes)?;
+        for encodable in self {
+            encodable.simple_encode(bytes)?;
         }
         Ok(())
     }
@@ -135,7 +200,6 @@ impl ByteEncode for Vec3 {
 impl ByteEncode for Quat {
     fn simple_encode(&self, bytes:&mut Vec<u8>) -> anyhow::Result<()> {
         self.to_array().simple_encode(bytes)?;
-        println!("{:?}", bytes);
         Ok(())
     }
 }
@@ -170,126 +234,72 @@ impl<T: ByteDecode> ByteDecode for Vec<T> {
 
 impl ByteDecode for f64 {
     fn simple_decode(decoder: &mut Decoder) -> Result<Self,Error> {
-        let byte_slice_result: Result<[u8;8], TryFromSliceError> = decoder.bytes[decoder.index..(decoder.index + 8)].try_into();
-
-        decoder.index += 8;
-        match byte_slice_result {
-            Ok(byte_slice) => return Ok(f64::from_le_bytes(byte_slice)),
-            Err(_) => return Err(Error::SimpleDecodeTryFrom)
-        }
+        let value = f64::from_le_bytes(decoder.read_array()?);
+        decoder.check_float(value.is_finite())?;
+        Ok(value)
     }
 }
 
 impl ByteDecode for f32 {
     fn simple_decode(decoder: &mut Decoder) -> Result<Self,Error> {
-        let byte_slice_result: Result<[u8;4], TryFromSliceError> = decoder.bytes[decoder.index..(decoder.index + 4)].try_into();
-
-        decoder.index += 4;
-        match byte_slice_result {
-            Ok(byte_slice) => return Ok(f32::from_le_bytes(byte_slice)),
-            Err(_) => return Err(Error::SimpleDecodeTryFrom)
-        }
+        let value = f32::from_le_bytes(decoder.read_array()?);
+        decoder.check_float(value.is_finite())?;
+        Ok(value)
     }
 }
 
 impl ByteDecode for u128 {
     fn simple_decode(decoder: &mut Decoder) -> Result<Self,Error> {
-        let byte_slice_result: Result<[u8;16], TryFromSliceError> = decoder.bytes[decoder.index..(decoder.index + 16)].try_into();
-
-        decoder.index += 16;
-        match byte_slice_result {
-            Ok(byte_slice) => return Ok(u128::from_le_bytes(byte_slice)),
-            Err(_) => return Err(Error::SimpleDecodeTryFrom)
-        }
+        Ok(u128::from_le_bytes(decoder.read_array()?))
     }
 }
 
 impl ByteDecode for u64 {
     fn simple_decode(decoder: &mut Decoder) -> Result<Self,Error> {
-        let byte_slice_result: Result<[u8;8], TryFromSliceError> = decoder.bytes[decoder.index..(decoder.index + 8)].try_into();
-
-        decoder.index += 8;
-        match byte_slice_result {
-            Ok(byte_slice) => return Ok(u64::from_le_bytes(byte_slice)),
-            Err(_) => return Err(Error::SimpleDecodeTryFrom)
-        }
+        Ok(u64::from_le_bytes(decoder.read_array()?))
     }
 }
 
 impl ByteDecode for u32 {
     fn simple_decode(decoder: &mut Decoder) -> Result<Self,Error> {
-        let byte_slice_result: Result<[u8;4], TryFromSliceError> = decoder.bytes[decoder.index..(decoder.index + 4)].try_into();
-
-        decoder.index += 4;
-        match byte_slice_result {
-            Ok(byte_slice) => return Ok(u32::from_le_bytes(byte_slice)),
-            Err(_) => return Err(Error::SimpleDecodeTryFrom)
-        }
+        Ok(u32::from_le_bytes(decoder.read_array()?))
     }
 }
 
 impl ByteDecode for u16 {
     fn simple_decode(decoder: &mut Decoder) -> Result<Self,Error> {
-        let byte_slice_result: Result<[u8;2], TryFromSliceError> = decoder.bytes[decoder.index..(decoder.index + 2)].try_into();
-
-        decoder.index += 2;
-        match byte_slice_result {
-            Ok(byte_slice) => return Ok(u16::from_le_bytes(byte_slice)),
-            Err(_) => return Err(Error::SimpleDecodeTryFrom)
-        }
+        Ok(u16::from_le_bytes(decoder.read_array()?))
     }
 }
 
 impl ByteDecode for u8 {
     fn simple_decode(decoder: &mut Decoder) -> Result<Self,Error> {
-        let byte = decoder.bytes[decoder.index];
-        decoder.index += 1;
-        return Ok(byte);
+        let [byte] = decoder.read_array()?;
+        Ok(byte)
     }
 }
 
 impl ByteDecode for i64 {
-    fn simple_decode(decoder: &mut Decoder) -> Result<Self,Error>
-        where Self: Sized {
-        
-        let byte_slice_result: Result<[u8;8], TryFromSliceError > = decoder.bytes[decoder.index..(decoder.index + 8)].try_into();
-        decoder.index += 8;
-        match byte_slice_result {
-            Ok(byte_slice) => return Ok(i64::from_le_bytes(byte_slice)),
-            Err(_) => return Err(Error::SimpleDecodeTryFrom)
-        }
+    fn simple_decode(decoder: &mut Decoder) -> Result<Self,Error> {
+        Ok(i64::from_le_bytes(decoder.read_array()?))
     }
 }
 
 impl ByteDecode for i32 {
     fn simple_decode(decoder: &mut Decoder) -> Result<Self,Error> {
-        let byte_slice_result: Result<[u8;4], TryFromSliceError> = decoder.bytes[decoder.index..(decoder.index + 4)].try_into();
-
-        decoder.index += 4;
-        match byte_slice_result {
-            Ok(byte_slice) => return Ok(i32::from_le_bytes(byte_slice)),
-            Err(_) => return Err(Error::SimpleDecodeTryFrom)
-        }
+        Ok(i32::from_le_bytes(decoder.read_array()?))
     }
 }
 
 impl ByteDecode for i16 {
     fn simple_decode(decoder: &mut Decoder) -> Result<Self,Error> {
-        let byte_slice_result: Result<[u8;2], TryFromSliceError> = decoder.bytes[decoder.index..(decoder.index + 2)].try_into();
-
-        decoder.index += 2;
-        match byte_slice_result {
-            Ok(byte_slice) => return Ok(i16::from_le_bytes(byte_slice)),
-            Err(_) => return Err(Error::SimpleDecodeTryFrom)
-        }
+        Ok(i16::from_le_bytes(decoder.read_array()?))
     }
 }
 
 impl ByteDecode for i8 {
     fn simple_decode(decoder: &mut Decoder) -> Result<Self,Error> {
-        let byte = [decoder.bytes[decoder.index]];
-        decoder.index += 1;
-        return Ok(i8::from_le_bytes(byte));
+        Ok(i8::from_le_bytes(decoder.read_array()?))
     }
 }
 
@@ -300,14 +310,13 @@ where
 {
     fn simple_decode(decoder: &mut Decoder) -> Result<Self,Error>
         where Self: Sized {
-        use std::mem::MaybeUninit;
-        let mut arr: [T; N] = unsafe { MaybeUninit::uninit().assume_init() };
+        let mut temp_vec = Vec::with_capacity(N);
 
-        for i in 0..N {
-            arr[i] = T::simple_decode(decoder)?; // Create and place each element
+        for _ in 0..N {
+            temp_vec.push(T::simple_decode(decoder)?);
         }
-        // Transmute the MaybeUninit array into a fully initialized array
-        Ok(arr)
+        // The length always matches N, so the conversion cannot fail
+        temp_vec.try_into().map_err(|_| Error::SimpleDecodeTryFrom)
     }
 }
 
@@ -474,7 +483,7 @@ impl ByteEncode for String {
     fn simple_encode(&self, bytes:&mut Vec<u8>) -> Result<(), Error> {
         let str_bytes = self.as_bytes();
         let byte_len = u64::try_from(str_bytes.len()).unwrap();
-        byte_len.simple_encode(bytes);
+        byte_len.simple_encode(bytes)?;
         bytes.extend_from_slice(str_bytes);
         Ok(())
     }
@@ -483,10 +492,8 @@ impl ByteEncode for String {
 impl ByteDecode for String {
     fn simple_decode(decoder: &mut Decoder) -> Result<Self, Error>
         where Self: Sized {
-        let byte_len = usize::try_from(u64::simple_decode(decoder)?).unwrap();
-        let str = String::from_utf8_lossy(&decoder.bytes[decoder.index..(decoder.index + byte_len)]);
-
-        decoder.index += byte_len;
+        let byte_len = usize::try_from(u64::simple_decode(decoder)?).map_err(|_| Error::SimpleDecodeTryFrom)?;
+        let str = String::from_utf8_lossy(decoder.read_slice(byte_len)?);
 
         Ok(str.into())
     }
@@ -549,10 +556,7 @@ mod tests {
         let mut bytes: Vec<u8> = Vec::new();
         let test_val = 5u64;
         test_val.simple_encode(&mut bytes)?;
-        let mut decoder = Decoder {
-            index: 0,
-            bytes,
-        };
+        let mut decoder = Decoder::new(bytes);
         let decoded_val = u64::simple_decode(&mut decoder)?;
 
         assert!(test_val == decoded_val, "Test Val: {} does not match Decoded Val: {}", test_val, decoded_val);
@@ -564,10 +568,7 @@ mod tests {
         let mut bytes: Vec<u8> = Vec::new();
         let test_val = 2.55f32;
         test_val.simple_encode(&mut bytes)?;
-        let mut decoder = Decoder {
-            index: 0,
-            bytes,
-        };
+        let mut decoder = Decoder::new(bytes);
         let decoded_val = f32::simple_decode(&mut decoder)?;
 
         assert!(test_val == decoded_val, "Test Val: {} does not match Decoded Val: {}", test_val, decoded_val);
@@ -584,10 +585,7 @@ mod tests {
             z: 2.5f32,
         };
         test_val.simple_encode(&mut bytes)?;
-        let mut decoder = Decoder {
-            index: 0,
-            bytes,
-        };
+        let mut decoder = Decoder::new(bytes);
         let decoded_val = Vec3::simple_decode(&mut decoder)?;
 
         assert!(test_val == decoded_val, "Test Val: {} does not match Decoded Val: {}", test_val, decoded_val);
@@ -605,10 +603,7 @@ mod tests {
             1f32
         );
         test_val.simple_encode(&mut bytes)?;
-        let mut decoder = Decoder {
-            index: 0,
-            bytes,
-        };
+        let mut decoder = Decoder::new(bytes);
         let decoded_val = Quat::simple_decode(&mut decoder)?;
 
         assert!(test_val == decoded_val, "Test Val: {} does not match Decoded Val: {}", test_val, decoded_val);
@@ -621,10 +616,7 @@ mod tests {
         let mut bytes: Vec<u8> = Vec::new();
         let test_val = Transform::default();
         test_val.simple_encode(&mut bytes)?;
-        let mut decoder = Decoder {
-            index: 0,
-            bytes,
-        };
+        let mut decoder = Decoder::new(bytes);
         let decoded_val = Transform::simple_decode(&mut decoder)?;
 
         assert!(test_val == decoded_val, "Test Val: {:?} does not match Decoded Val: {:?}", test_val, decoded_val);
@@ -643,13 +635,157 @@ mod tests {
         ];
 
         test_val.simple_encode(&mut bytes)?;
-        let mut decoder = Decoder {
-            index: 0,
-            bytes,
-        };
+        let mut decoder = Decoder::new(bytes);
         let decoded_val = ValType::simple_decode(&mut decoder)?;
         assert!(test_val == decoded_val, "Test Val: {:?} does not match Decoded Val: {:?}", test_val, decoded_val);
         Ok(())
     }
+
+    #[test]
+    fn truncated_input() -> Result<(), Error> {
+        let mut bytes: Vec<u8> = Vec::new();
+        "truncated".to_string().simple_encode(&mut bytes)?;
+        bytes.truncate(bytes.len() - 2);
+        let mut decoder = Decoder::new(bytes);
+
+        match String::simple_decode(&mut decoder) {
+            Err(Error::UnexpectedEof { needed: 9, remaining: 7 }) => {},
+            result => panic!("Expected truncation error, got {:?}", result),
+        }
+        assert!(matches!(u32::simple_decode(&mut Decoder::new(vec![1, 2])), Err(Error::UnexpectedEof { .. })));
+        assert!(matches!(u8::simple_decode(&mut Decoder::new(Vec::new())), Err(Error::UnexpectedEof { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn finite_floats() -> Result<(), Error> {
+        for test_val in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            let mut bytes: Vec<u8> = Vec::new();
+            test_val.simple_encode(&mut bytes)?;
+
+            let lenient_val = f32::simple_decode(&mut Decoder::new(bytes.clone()))?;
+            assert!(lenient_val.is_nan() || lenient_val.is_infinite());
+
+            let mut decoder = Decoder::with_options(bytes, DecoderOptions { finite_floats: true });
+            assert!(matches!(f32::simple_decode(&mut decoder), Err(Error::NonFiniteFloat)));
+        }
+
+        let mut bytes: Vec<u8> = Vec::new();
+        [1.5f64, f64::NAN].simple_encode(&mut bytes)?;
+        let mut decoder = Decoder::with_options(bytes, DecoderOptions { finite_floats: true });
+        assert!(matches!(<[f64; 2]>::simple_decode(&mut decoder), Err(Error::NonFiniteFloat)));
+        Ok(())
+    }
+
+    #[test]
+    fn canonical_nan() -> Result<(), Error> {
+        let payload_nan = f32::from_bits(0x7fc0_1234);
+        let mut bytes: Vec<u8> = Vec::new();
+        payload_nan.canonical_nan().simple_encode(&mut bytes)?;
+        assert_eq!(bytes, CANONICAL_NAN_F32_BITS.to_le_bytes());
+
+        let negative_nan = -f64::NAN;
+        assert_eq!(negative_nan.canonical_nan().to_bits(), CANONICAL_NAN_F64_BITS);
+        assert_eq!(2.5f64.canonical_nan(), 2.5f64);
+        Ok(())
+    }
+
+    #[test]
+    fn varint_transport() -> Result<(), Error> {
+        for test_val in [0u64, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            let mut bytes: Vec<u8> = Vec::new();
+            VarInt(test_val).simple_encode(&mut bytes)?;
+            let mut decoder = Decoder::new(bytes);
+            let decoded_val = VarInt::simple_decode(&mut decoder)?;
+
+            assert!(VarInt(test_val) == decoded_val, "Test Val: {} does not match Decoded Val: {:?}", test_val, decoded_val);
+            assert_eq!(decoder.index, decoder.bytes.len());
+        }
+
+        let mut bytes: Vec<u8> = Vec::new();
+        VarInt(300).simple_encode(&mut bytes)?;
+        assert_eq!(bytes, vec![0xac, 0x02]);
+        Ok(())
+    }
+
+    #[test]
+    fn checksums() {
+        assert_eq!(Crc32::compute(b"123456789"), 0xcbf4_3926);
+        assert_eq!(Crc32::compute(b""), 0);
+        assert_eq!(Sum8::compute(&[0xff, 0x02]), 0x01);
+        assert_eq!(Xor8::compute(&[0x0f, 0xf0, 0x01]), 0xfe);
+    }
+
+    #[test]
+    fn frames() -> Result<(), Error> {
+        let writer = FrameWriter::with_max_frame_size(16);
+        let mut bytes: Vec<u8> = Vec::new();
+        writer.write(&5u32, &mut bytes)?;
+        writer.write(&String::from("hi"), &mut bytes)?;
+        writer.write(&7u16, &mut bytes)?;
+        assert_eq!(bytes[..8], [4u8, 0, 0, 0, 5, 0, 0, 0]);
+
+        // Several frames in one buffer, and a frame split across pushes
+        let mut reader = FrameReader::with_max_frame_size(16);
+        reader.push(&bytes[..24]);
+        assert_eq!(reader.read::<u32>()?, Some(5u32));
+        assert_eq!(reader.read::<String>()?, Some(String::from("hi")));
+        assert_eq!(reader.read::<u16>()?, None);
+        reader.push(&bytes[24..]);
+        assert_eq!(reader.read::<u16>()?, Some(7u16));
+        assert_eq!(reader.buffered(), 0);
+
+        match writer.write(&vec![0u64; 2], &mut bytes) {
+            Err(Error::FrameTooLarge { size: 18, max: 16 }) => {},
+            result => panic!("Expected frame too large, got {:?}", result),
+        }
+        assert_eq!(bytes.len(), 28);
+
+        // An oversize frame is reported once and skipped, even before all of it has arrived
+        let mut stream: Vec<u8> = 100u32.to_le_bytes().to_vec();
+        stream.extend_from_slice(&[0xaa; 100]);
+        FrameWriter::new().write(&9u8, &mut stream)?;
+        let mut reader = FrameReader::with_max_frame_size(8);
+        reader.push(&stream[..50]);
+        match reader.next_frame() {
+            Err(Error::FrameTooLarge { size: 100, max: 8 }) => {},
+            result => panic!("Expected frame too large, got {:?}", result),
+        }
+        assert_eq!(reader.next_frame()?, None);
+        reader.push(&stream[50..]);
+        assert_eq!(reader.read::<u8>()?, Some(9u8));
+        Ok(())
+    }
+
+    #[test]
+    fn blocking_messages() -> Result<(), Error> {
+        // Through trait objects, as Vec and Cursor also implement the tokio traits
+        let mut stream: Vec<u8> = Vec::new();
+        let writer: &mut dyn std::io::Write = &mut stream;
+        writer.write_message(&42u64)?;
+        writer.write_message(&vec![1i16, -2, 3])?;
+        writer.write_message(&String::from("hello"))?;
+        writer.write_message(&Some(2.5f32))?;
+        writer.write_message(&None::<u8>)?;
+
+        let mut cursor = std::io::Cursor::new(stream);
+        let reader: &mut dyn std::io::Read = &mut cursor;
+        assert_eq!(reader.read_message::<u64>()?, 42u64);
+        assert_eq!(reader.read_message::<Vec<i16>>()?, vec![1i16, -2, 3]);
+        assert_eq!(reader.read_message::<String>()?, String::from("hello"));
+        assert_eq!(reader.read_message::<Option<f32>>()?, Some(2.5f32));
+        assert_eq!(reader.read_message::<Option<u8>>()?, None);
+        assert!(matches!(reader.read_message::<u8>(), Err(Error::Io(_))));
+
+        let mut oversize_cursor = std::io::Cursor::new(FrameWriter::new().frame(&vec![0u8; 64])?);
+        let oversize: &mut dyn std::io::Read = &mut oversize_cursor;
+        assert!(matches!(oversize.read_message_with_max::<Vec<u8>>(16), Err(Error::FrameTooLarge { size: 66, max: 16 })));
+
+        let mut nan_cursor = std::io::Cursor::new(FrameWriter::new().frame(&f32::NAN)?);
+        let nan: &mut dyn std::io::Read = &mut nan_cursor;
+        let options = DecoderOptions { finite_floats: true };
+        assert!(matches!(nan.read_message_with_options::<f32>(DEFAULT_MAX_FRAME_SIZE, options), Err(Error::NonFiniteFloat)));
+        Ok(())
+    }
 }
 
@@ -0,0 +1,92 @@
+use std::any::{type_name, TypeId};
+use std::collections::HashMap;
+
+use crate::{ByteDecode, ByteEncode, Decoder, Error};
+
+/// A message type with a stable wire id, for `MessageRegistry::register`
+pub trait Message: ByteEncode + ByteDecode + 'static {
+    const ID: u16;
+}
+
+type Handler<C> = Box<dyn FnMut(&mut C, &mut Decoder) -> Result<(), Error> + Send>;
+
+struct Entry<C> {
+    type_name: &'static str,
+    handler: Handler<C>,
+}
+
+/// Maps `u16` ids to message types and their handlers.
+///
+/// Messages are written as `[u16 id][payload]`, usually inside a frame. Incoming
+/// messages are decoded as the type registered for their id and passed to its
+/// handler along with a shared context `C`. Every module or plugin can add its
+/// own messages given `&mut MessageRegistry<C>`.
+pub struct MessageRegistry<C = ()> {
+    entries: HashMap<u16, Entry<C>>,
+    ids: HashMap<TypeId, u16>,
+}
+
+impl<C> Default for MessageRegistry<C> {
+    fn default() -> Self {
+        MessageRegistry { entries: HashMap::new(), ids: HashMap::new() }
+    }
+}
+
+impl<C> MessageRegistry<C> {
+    pub fn new() -> Self {
+        MessageRegistry::default()
+    }
+
+    /// Registers `T` under its `Message::ID`
+    pub fn register<T: Message>(&mut self, handler: impl FnMut(&mut C, T) + Send + 'static) -> Result<(), Error> {
+        self.register_with_id(T::ID, handler)
+    }
+
+    /// Registers `T` under `id`, for types that don't implement `Message`.
+    /// Fails with `Error::DuplicateMessageId` if the id or the type is already registered.
+    pub fn register_with_id<T: ByteDecode + 'static>(&mut self, id: u16, mut handler: impl FnMut(&mut C, T) + Send + 'static) -> Result<(), Error> {
+        if self.entries.contains_key(&id) {
+            return Err(Error::DuplicateMessageId(id));
+        }
+        if let Some(existing) = self.ids.get(&TypeId::of::<T>()) {
+            return Err(Error::DuplicateMessageId(*existing));
+        }
+        let handler: Handler<C> = Box::new(move |context, decoder| {
+            handler(context, T::simple_decode(decoder)?);
+            Ok(())
+        });
+        self.entries.insert(id, Entry { type_name: type_name::<T>(), handler });
+        self.ids.insert(TypeId::of::<T>(), id);
+        Ok(())
+    }
+
+    pub fn is_registered(&self, id: u16) -> bool {
+        self.entries.contains_key(&id)
+    }
+
+    /// Id `T` was registered under
+    pub fn id_of<T: 'static>(&self) -> Option<u16> {
+        self.ids.get(&TypeId::of::<T>()).copied()
+    }
+
+    /// Name of the type registered under `id`, for logging
+    pub fn type_name_of(&self, id: u16) -> Option<&'static str> {
+        self.entries.get(&id).map(|entry| entry.type_name)
+    }
+
+    /// Writes `[u16 id][payload]` for a registered message type
+    pub fn encode<T: ByteEncode + 'static>(&self, message: &T, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        let id = self.id_of::<T>().ok_or(Error::UnregisteredMessage { type_name: type_name::<T>() })?;
+        id.simple_encode(bytes)?;
+        message.simple_encode(bytes)
+    }
+
+    /// Decodes one `[u16 id][payload]` message and runs the handler for its id.
+    /// Unknown ids fail with `Error::UnknownMessageId` and leave the handlers untouched.
+    pub fn dispatch(&mut self, context: &mut C, message: &[u8]) -> Result<(), Error> {
+        let mut decoder = Decoder::new(message.to_vec());
+        let id = u16::simple_decode(&mut decoder)?;
+        let entry = self.entries.get_mut(&id).ok_or(Error::UnknownMessageId(id))?;
+        (entry.handler)(context, &mut decoder)
+    }
+}
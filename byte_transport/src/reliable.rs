@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use crate::{ByteDecode, ByteEncode, Connection, Decoder, DecoderOptions, Delivery, Error, ReliableConfig, Transport, UdpTransport};
+
+// The only channel of the underlying connection
+const CHANNEL: u8 = 0;
+
+/// Reliable, ordered delivery of messages over an unreliable transport, normally UDP.
+///
+/// This is a `Connection` with a single `Delivery::ReliableOrdered` channel, see there
+/// for how packets are sequenced, acked and resent. The receiver drops duplicates and
+/// holds back messages that arrive early until the gap is filled.
+///
+/// Nothing happens on the network outside `update`, which should be called every tick.
+pub struct ReliableChannel<T = UdpTransport> {
+    connection: Connection<T>,
+}
+
+impl<T: Transport> ReliableChannel<T> {
+    pub fn new(transport: T) -> Self {
+        ReliableChannel::with_config(transport, ReliableConfig::default())
+    }
+
+    pub fn with_config(transport: T, config: ReliableConfig) -> Self {
+        let mut connection = Connection::with_config(transport, config);
+        connection.add_channel("reliable", Delivery::ReliableOrdered).expect("a new connection has no channels");
+        ReliableChannel { connection }
+    }
+
+    /// See [`Connection::with_decoder_options`]
+    pub fn with_decoder_options(self, options: DecoderOptions) -> Self {
+        ReliableChannel { connection: self.connection.with_decoder_options(options) }
+    }
+
+    /// See [`Connection::send`]
+    pub fn send<M: ByteEncode>(&mut self, message: &M) -> Result<(), Error> {
+        let mut payload = Vec::new();
+        message.simple_encode(&mut payload)?;
+        self.send_bytes(payload)
+    }
+
+    pub fn send_bytes(&mut self, payload: Vec<u8>) -> Result<(), Error> {
+        self.connection.send_on(CHANNEL, payload)
+    }
+
+    /// See [`Connection::recv`]
+    pub fn recv<M: ByteDecode>(&mut self) -> Result<Option<M>, Error> {
+        let options = self.connection.decoder_options();
+        self.recv_bytes().map(|payload| M::simple_decode(&mut Decoder::with_options(payload, options))).transpose()
+    }
+
+    pub fn recv_bytes(&mut self) -> Option<Vec<u8>> {
+        self.connection.recv_on(CHANNEL)
+    }
+
+    /// See [`Connection::update`]
+    pub fn update(&mut self) -> Result<(), Error> {
+        self.connection.update()
+    }
+
+    /// See [`Connection::rtt`]
+    pub fn rtt(&self) -> Duration {
+        self.connection.rtt()
+    }
+
+    /// See [`Connection::in_flight`]
+    pub fn in_flight(&self) -> usize {
+        self.connection.in_flight()
+    }
+
+    pub fn config(&self) -> &ReliableConfig {
+        self.connection.config()
+    }
+
+    pub fn decoder_options(&self) -> DecoderOptions {
+        self.connection.decoder_options()
+    }
+
+    /// See [`Connection::dropped_packets`]
+    pub fn dropped_packets(&self) -> usize {
+        self.connection.dropped_packets()
+    }
+
+    /// See [`Connection::last_packet_error`]
+    pub fn last_packet_error(&self) -> Option<&Error> {
+        self.connection.last_packet_error()
+    }
+
+    pub fn get_ref(&self) -> &T {
+        self.connection.get_ref()
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.connection.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.connection.into_inner()
+    }
+}
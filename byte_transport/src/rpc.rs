@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use crate::{ByteDecode, ByteEncode, Decoder, DecoderOptions, Error};
+
+/// A request whose handler answers with `Response`, sent under the method id `METHOD`
+pub trait Request: ByteEncode + ByteDecode + 'static {
+    type Response: ByteEncode + ByteDecode + 'static;
+    const METHOD: u16;
+}
+
+// First byte of every RPC frame
+const REQUEST: u8 = 0;
+const RESPONSE_OK: u8 = 1;
+const RESPONSE_ERR: u8 = 2;
+
+/// True for frames answered by `RpcServer` and false for frames meant for `RpcClient`,
+/// for connections that carry calls in both directions
+pub fn is_request(frame: &[u8]) -> bool {
+    frame.first() == Some(&REQUEST)
+}
+
+#[derive(Default)]
+struct Slot {
+    response: Option<Result<Vec<u8>, Error>>,
+    waker: Option<Waker>,
+    deadline: Option<Instant>,
+}
+
+#[derive(Default)]
+struct Calls {
+    slots: Mutex<HashMap<u32, Slot>>,
+    // Wakes blocking waits when any response arrives
+    arrived: Condvar,
+}
+
+impl Calls {
+    fn lock(&self) -> MutexGuard<'_, HashMap<u32, Slot>> {
+        // A panic while holding the lock can't leave the map half updated, so carry on
+        self.slots.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn resolve(&self, call_id: u32, response: Result<Vec<u8>, Error>) -> bool {
+        let mut slots = self.lock();
+        // A call that already timed out keeps its `Error::Timeout`
+        let Some(slot) = slots.get_mut(&call_id).filter(|slot| slot.response.is_none()) else {
+            return false;
+        };
+        slot.response = Some(response);
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+        self.arrived.notify_all();
+        true
+    }
+}
+
+type SendFn = Box<dyn Fn(Vec<u8>) -> Result<(), Error> + Send + Sync>;
+
+/// Sends requests as `[0][u32 call_id][u16 method][payload]` frames and matches up the responses.
+///
+/// Frames go out through the `send` function given to `new`, and every response
+/// frame received must be passed to `handle_response`.
+pub struct RpcClient {
+    send: SendFn,
+    next_call_id: AtomicU32,
+    calls: Arc<Calls>,
+    options: DecoderOptions,
+}
+
+impl RpcClient {
+    pub fn new(send: impl Fn(Vec<u8>) -> Result<(), Error> + Send + Sync + 'static) -> Self {
+        RpcClient { send: Box::new(send), next_call_id: AtomicU32::new(1), calls: Arc::default(), options: DecoderOptions::default() }
+    }
+
+    /// Options used for the `Decoder` of every response
+    pub fn with_decoder_options(mut self, options: DecoderOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sends `request`, returning a handle that resolves to its response
+    pub fn call<R: Request>(&self, request: &R) -> Result<PendingCall<R::Response>, Error> {
+        self.call_inner(request, None)
+    }
+
+    /// Like `call`, failing with `Error::Timeout` if no response arrives within `timeout`.
+    /// Blocking waits time out on their own; an awaited call only fails once it is polled
+    /// after the deadline, and `expire_timeouts` is what wakes it to be polled.
+    pub fn call_with_timeout<R: Request>(&self, request: &R, timeout: Duration) -> Result<PendingCall<R::Response>, Error> {
+        self.call_inner(request, Some(Instant::now() + timeout))
+    }
+
+    fn call_inner<R: Request>(&self, request: &R, deadline: Option<Instant>) -> Result<PendingCall<R::Response>, Error> {
+        let call_id = self.next_call_id.fetch_add(1, Ordering::Relaxed);
+        let mut frame = vec![REQUEST];
+        call_id.simple_encode(&mut frame)?;
+        R::METHOD.simple_encode(&mut frame)?;
+        request.simple_encode(&mut frame)?;
+
+        self.calls.lock().insert(call_id, Slot { deadline, ..Slot::default() });
+        let pending = PendingCall { call_id, calls: self.calls.clone(), options: self.options, marker: PhantomData };
+        // Dropping `pending` on failure removes the slot again
+        (self.send)(frame)?;
+        Ok(pending)
+    }
+
+    /// Resolves the call a response frame answers. Returns false if the call was
+    /// cancelled or timed out, in which case the response is dropped.
+    pub fn handle_response(&self, frame: &[u8]) -> Result<bool, Error> {
+        let mut decoder = Decoder::new(frame.to_vec());
+        let kind = u8::simple_decode(&mut decoder)?;
+        let call_id = u32::simple_decode(&mut decoder)?;
+        let response = match kind {
+            RESPONSE_OK => Ok(decoder.bytes.split_off(decoder.index)),
+            RESPONSE_ERR => Err(Error::Remote(String::simple_decode(&mut decoder)?)),
+            _ => return Err(Error::SimpleDecodeError(format!("Not an RPC response frame, kind {kind}"))),
+        };
+        Ok(self.calls.resolve(call_id, response))
+    }
+
+    /// Fails every call past its deadline with `Error::Timeout`. Async callers have no
+    /// timer of their own, so this should run regularly, e.g. once per network tick.
+    pub fn expire_timeouts(&self) {
+        let now = Instant::now();
+        let mut slots = self.calls.lock();
+        for slot in slots.values_mut() {
+            if slot.response.is_none() && slot.deadline.is_some_and(|deadline| deadline <= now) {
+                slot.response = Some(Err(Error::Timeout));
+                if let Some(waker) = slot.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+        self.calls.arrived.notify_all();
+    }
+
+    /// Calls still waiting for a response
+    pub fn pending_calls(&self) -> usize {
+        self.calls.lock().len()
+    }
+}
+
+/// Handle to a call in flight. Await it, or block with `wait`. Dropping it cancels the call.
+pub struct PendingCall<T> {
+    call_id: u32,
+    calls: Arc<Calls>,
+    options: DecoderOptions,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T: ByteDecode> PendingCall<T> {
+    pub fn call_id(&self) -> u32 {
+        self.call_id
+    }
+
+    /// Blocks until the response arrives or the call's deadline passes
+    pub fn wait(self) -> Result<T, Error> {
+        self.wait_until(None)
+    }
+
+    /// Blocks for at most `timeout`, failing with `Error::Timeout` after that
+    pub fn wait_timeout(self, timeout: Duration) -> Result<T, Error> {
+        self.wait_until(Some(Instant::now() + timeout))
+    }
+
+    fn wait_until(self, limit: Option<Instant>) -> Result<T, Error> {
+        let mut slots = self.calls.lock();
+        loop {
+            let slot = slots.get_mut(&self.call_id).ok_or(Error::Cancelled)?;
+            if let Some(response) = slot.response.take() {
+                drop(slots);
+                return decode_response(response, self.options);
+            }
+            let deadline = match (slot.deadline, limit) {
+                (Some(deadline), Some(limit)) => Some(deadline.min(limit)),
+                (deadline, limit) => deadline.or(limit),
+            };
+            slots = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if deadline <= now {
+                        return Err(Error::Timeout);
+                    }
+                    self.calls.arrived.wait_timeout(slots, deadline - now).unwrap_or_else(|poisoned| poisoned.into_inner()).0
+                },
+                None => self.calls.arrived.wait(slots).unwrap_or_else(|poisoned| poisoned.into_inner()),
+            };
+        }
+    }
+
+    /// Gives up on the call, a response arriving later is dropped
+    pub fn cancel(self) {}
+}
+
+fn decode_response<T: ByteDecode>(response: Result<Vec<u8>, Error>, options: DecoderOptions) -> Result<T, Error> {
+    T::simple_decode(&mut Decoder::with_options(response?, options))
+}
+
+impl<T: ByteDecode> Future for PendingCall<T> {
+    type Output = Result<T, Error>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slots = self.calls.lock();
+        let Some(slot) = slots.get_mut(&self.call_id) else {
+            return Poll::Ready(Err(Error::Cancelled));
+        };
+        match slot.response.take() {
+            Some(response) => {
+                drop(slots);
+                Poll::Ready(decode_response(response, self.options))
+            },
+            None if slot.deadline.is_some_and(|deadline| deadline <= Instant::now()) => Poll::Ready(Err(Error::Timeout)),
+            None => {
+                slot.waker = Some(context.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
+}
+
+impl<T> Drop for PendingCall<T> {
+    fn drop(&mut self) {
+        self.calls.lock().remove(&self.call_id);
+    }
+}
+
+type Handler<C> = Box<dyn FnMut(&mut C, &mut Decoder) -> Result<Vec<u8>, Error> + Send>;
+
+/// Answers request frames from an `RpcClient` with the handler registered for their method
+pub struct RpcServer<C = ()> {
+    handlers: HashMap<u16, Handler<C>>,
+    options: DecoderOptions,
+}
+
+impl<C> Default for RpcServer<C> {
+    fn default() -> Self {
+        RpcServer { handlers: HashMap::new(), options: DecoderOptions::default() }
+    }
+}
+
+impl<C> RpcServer<C> {
+    pub fn new() -> Self {
+        RpcServer::default()
+    }
+
+    /// Options used for the `Decoder` of every request
+    pub fn with_decoder_options(mut self, options: DecoderOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Handles `R` requests, failing with `Error::DuplicateMethod` if `R::METHOD` already has a handler.
+    /// An `Err` from the handler reaches the caller as `Error::Remote`.
+    pub fn register<R: Request>(&mut self, mut handler: impl FnMut(&mut C, R) -> Result<R::Response, Error> + Send + 'static) -> Result<(), Error> {
+        if self.handlers.contains_key(&R::METHOD) {
+            return Err(Error::DuplicateMethod(R::METHOD));
+        }
+        self.handlers.insert(R::METHOD, Box::new(move |context, decoder| {
+            let response = handler(context, R::simple_decode(decoder)?)?;
+            let mut bytes = Vec::new();
+            response.simple_encode(&mut bytes)?;
+            Ok(bytes)
+        }));
+        Ok(())
+    }
+
+    /// Runs the handler for a request frame, returning the response frame to send back.
+    /// Unknown methods and failed handlers are answered with an error response so the
+    /// caller isn't left waiting; only a frame too broken to hold a call id fails here.
+    pub fn handle_request(&mut self, context: &mut C, frame: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut decoder = Decoder::with_options(frame.to_vec(), self.options);
+        let kind = u8::simple_decode(&mut decoder)?;
+        if kind != REQUEST {
+            return Err(Error::SimpleDecodeError(format!("Not an RPC request frame, kind {kind}")));
+        }
+        let call_id = u32::simple_decode(&mut decoder)?;
+        let result = u16::simple_decode(&mut decoder).and_then(|method| match self.handlers.get_mut(&method) {
+            Some(handler) => handler(context, &mut decoder),
+            None => Err(Error::UnknownMessageId(method)),
+        });
+
+        let mut response = Vec::new();
+        match result {
+            Ok(payload) => {
+                RESPONSE_OK.simple_encode(&mut response)?;
+                call_id.simple_encode(&mut response)?;
+                response.extend_from_slice(&payload);
+            },
+            Err(error) => {
+                RESPONSE_ERR.simple_encode(&mut response)?;
+                call_id.simple_encode(&mut response)?;
+                remote_message(error).simple_encode(&mut response)?;
+            },
+        }
+        Ok(response)
+    }
+}
+
+// Errors travel as text, an `Error::Remote` from a nested call keeps its original message
+fn remote_message(error: Error) -> String {
+    match error {
+        Error::Remote(message) => message,
+        error => format!("{error:?}"),
+    }
+}
@@ -0,0 +1,255 @@
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+
+use crate::{ByteDecode, ByteEncode, Decoder, DecoderOptions, Error, FrameReader, FrameWriter, DEFAULT_MAX_FRAME_SIZE, FRAME_HEADER_LEN};
+
+/// Largest payload that fits in a single UDP datagram over IPv4
+pub const MAX_DATAGRAM_SIZE: usize = 65_507;
+
+/// Moves whole encoded messages between two peers.
+///
+/// Implementations only deal in payloads, each `recv_bytes` returns exactly what one
+/// `send_bytes` on the other side was given; `send` and `recv` add the encoding on top.
+pub trait Transport {
+    fn send_bytes(&mut self, payload: &[u8]) -> Result<(), Error>;
+
+    /// Blocks until the next payload arrives
+    fn recv_bytes(&mut self) -> Result<Vec<u8>, Error>;
+
+    /// Takes the next payload if one is waiting. The default treats `WouldBlock` and
+    /// `TimedOut` from `recv_bytes` as nothing waiting, which only suits transports that
+    /// receive each payload whole, like datagram sockets that are non-blocking or have a
+    /// read timeout. Stream transports must buffer partial payloads themselves.
+    fn try_recv_bytes(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        match self.recv_bytes() {
+            Ok(payload) => Ok(Some(payload)),
+            Err(Error::Io(error)) if matches!(error.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn send<T: ByteEncode>(&mut self, message: &T) -> Result<(), Error>
+    where
+        Self: Sized,
+    {
+        let mut payload = Vec::new();
+        message.simple_encode(&mut payload)?;
+        self.send_bytes(&payload)
+    }
+
+    fn recv<T: ByteDecode>(&mut self) -> Result<T, Error>
+    where
+        Self: Sized,
+    {
+        self.recv_with_options(DecoderOptions::default())
+    }
+
+    /// Like `recv`, decoding the payload with `options`
+    fn recv_with_options<T: ByteDecode>(&mut self, options: DecoderOptions) -> Result<T, Error>
+    where
+        Self: Sized,
+    {
+        T::simple_decode(&mut Decoder::with_options(self.recv_bytes()?, options))
+    }
+
+    fn try_recv<T: ByteDecode>(&mut self) -> Result<Option<T>, Error>
+    where
+        Self: Sized,
+    {
+        self.try_recv_with_options(DecoderOptions::default())
+    }
+
+    /// Like `try_recv`, decoding the payload with `options`
+    fn try_recv_with_options<T: ByteDecode>(&mut self, options: DecoderOptions) -> Result<Option<T>, Error>
+    where
+        Self: Sized,
+    {
+        self.try_recv_bytes()?.map(|payload| T::simple_decode(&mut Decoder::with_options(payload, options))).transpose()
+    }
+}
+
+/// Sends payloads over a TCP stream as `FrameWriter` frames.
+///
+/// Received bytes are buffered until a frame is complete, so `try_recv_bytes` can be
+/// mixed with `recv_bytes` and a read timeout can expire mid-frame without losing
+/// the stream's place. Each sets the stream to the blocking mode it needs.
+pub struct TcpTransport {
+    stream: TcpStream,
+    writer: FrameWriter,
+    reader: FrameReader,
+}
+
+impl TcpTransport {
+    pub fn new(stream: TcpStream) -> Self {
+        TcpTransport::with_max_frame_size(stream, DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    /// Fails with `Error::FrameTooLarge` for payloads over `max_frame_size` in either direction
+    pub fn with_max_frame_size(stream: TcpStream, max_frame_size: usize) -> Self {
+        // Messages are written in one go, so waiting to coalesce them only adds latency
+        let _ = stream.set_nodelay(true);
+        TcpTransport { stream, writer: FrameWriter::with_max_frame_size(max_frame_size), reader: FrameReader::with_max_frame_size(max_frame_size) }
+    }
+
+    pub fn connect(address: impl ToSocketAddrs) -> Result<Self, Error> {
+        Ok(TcpTransport::new(TcpStream::connect(address)?))
+    }
+
+    pub fn get_ref(&self) -> &TcpStream {
+        &self.stream
+    }
+
+    /// The stream, dropping any part of a frame already read from it
+    pub fn into_inner(self) -> TcpStream {
+        self.stream
+    }
+
+    // Reads whatever the stream has into the frame reader, false if it would block
+    fn fill(&mut self, nonblocking: bool) -> Result<bool, Error> {
+        let mut chunk = [0u8; 8192];
+        self.stream.set_nonblocking(nonblocking)?;
+        match self.stream.read(&mut chunk) {
+            Ok(0) => Err(Error::Io(io::ErrorKind::UnexpectedEof.into())),
+            Ok(read) => {
+                self.reader.push(&chunk[..read]);
+                Ok(true)
+            },
+            Err(error) if nonblocking && error.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => Ok(true),
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send_bytes(&mut self, payload: &[u8]) -> Result<(), Error> {
+        let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+        self.writer.write_payload(payload, &mut frame)?;
+        self.stream.write_all(&frame)?;
+        Ok(())
+    }
+
+    fn recv_bytes(&mut self) -> Result<Vec<u8>, Error> {
+        loop {
+            if let Some(payload) = self.reader.next_frame()? {
+                return Ok(payload);
+            }
+            self.fill(false)?;
+        }
+    }
+
+    fn try_recv_bytes(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        loop {
+            if let Some(payload) = self.reader.next_frame()? {
+                return Ok(Some(payload));
+            }
+            if !self.fill(true)? {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+/// Sends each payload as one datagram to a single peer. Datagrams can be lost, duplicated
+/// or reordered, and datagrams from any other address are ignored.
+///
+/// `recv_bytes` and `try_recv_bytes` each set the socket to the blocking mode they need.
+pub struct UdpTransport {
+    socket: UdpSocket,
+    peer: SocketAddr,
+    buffer: Vec<u8>,
+}
+
+impl UdpTransport {
+    pub fn new(socket: UdpSocket, peer: SocketAddr) -> Self {
+        UdpTransport { socket, peer, buffer: vec![0u8; MAX_DATAGRAM_SIZE] }
+    }
+
+    /// Binds a socket on `local` that talks to `peer`
+    pub fn bind(local: impl ToSocketAddrs, peer: SocketAddr) -> Result<Self, Error> {
+        Ok(UdpTransport::new(UdpSocket::bind(local)?, peer))
+    }
+
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    pub fn get_ref(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    pub fn into_inner(self) -> UdpSocket {
+        self.socket
+    }
+
+    fn receive(&mut self, nonblocking: bool) -> Result<Vec<u8>, Error> {
+        self.socket.set_nonblocking(nonblocking)?;
+        loop {
+            let (size, from) = self.socket.recv_from(&mut self.buffer)?;
+            if from == self.peer {
+                return Ok(self.buffer[..size].to_vec());
+            }
+        }
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send_bytes(&mut self, payload: &[u8]) -> Result<(), Error> {
+        if payload.len() > MAX_DATAGRAM_SIZE {
+            return Err(Error::FrameTooLarge { size: payload.len(), max: MAX_DATAGRAM_SIZE });
+        }
+        self.socket.send_to(payload, self.peer)?;
+        Ok(())
+    }
+
+    fn recv_bytes(&mut self) -> Result<Vec<u8>, Error> {
+        self.receive(false)
+    }
+
+    fn try_recv_bytes(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        match self.receive(true) {
+            Ok(payload) => Ok(Some(payload)),
+            Err(Error::Io(error)) if error.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+/// One end of an in-process connection made by `MemoryTransport::pair`, delivering
+/// payloads in order without touching the network, for deterministic tests
+pub struct MemoryTransport {
+    sender: Sender<Vec<u8>>,
+    receiver: Receiver<Vec<u8>>,
+}
+
+impl MemoryTransport {
+    pub fn pair() -> (MemoryTransport, MemoryTransport) {
+        let (left_sender, right_receiver) = mpsc::channel();
+        let (right_sender, left_receiver) = mpsc::channel();
+        (MemoryTransport { sender: left_sender, receiver: left_receiver }, MemoryTransport { sender: right_sender, receiver: right_receiver })
+    }
+}
+
+// The other end was dropped, reported the way a closed socket would be
+fn disconnected() -> Error {
+    Error::Io(io::Error::from(io::ErrorKind::ConnectionReset))
+}
+
+impl Transport for MemoryTransport {
+    fn send_bytes(&mut self, payload: &[u8]) -> Result<(), Error> {
+        self.sender.send(payload.to_vec()).map_err(|_| disconnected())
+    }
+
+    fn recv_bytes(&mut self) -> Result<Vec<u8>, Error> {
+        self.receiver.recv().map_err(|_| disconnected())
+    }
+
+    fn try_recv_bytes(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        match self.receiver.try_recv() {
+            Ok(payload) => Ok(Some(payload)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(disconnected()),
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use crate::{ByteDecode, ByteEncode, Decoder, Error};
+
+/// Unsigned LEB128 integer: 7 bits per byte, high bit set while more bytes follow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct VarInt(pub u64);
+
+const MAX_VARINT_BYTES: usize = 10;
+
+impl ByteEncode for VarInt {
+    fn simple_encode(&self, bytes:&mut Vec<u8>) -> Result<(), Error> {
+        let mut value = self.0;
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(byte);
+                return Ok(());
+            }
+            bytes.push(byte | 0x80);
+        }
+    }
+}
+
+impl ByteDecode for VarInt {
+    fn simple_decode(decoder: &mut Decoder) -> Result<Self, Error> {
+        let mut value = 0u64;
+        for index in 0..MAX_VARINT_BYTES {
+            let byte = u8::simple_decode(decoder)?;
+            let bits = u64::from(byte & 0x7f);
+            // The tenth byte may only carry the single remaining bit of a u64
+            if index == MAX_VARINT_BYTES - 1 && bits > 1 {
+                return Err(Error::SimpleDecodeError("VarInt overflows u64".to_string()));
+            }
+            value |= bits << (7 * index);
+            if byte & 0x80 == 0 {
+                return Ok(VarInt(value));
+            }
+        }
+        Err(Error::SimpleDecodeError("VarInt longer than 10 bytes".to_string()))
+    }
+}
+
+impl From<u64> for VarInt {
+    fn from(value: u64) -> Self {
+        VarInt(value)
+    }
+}
+
+impl From<VarInt> for u64 {
+    fn from(value: VarInt) -> Self {
+        value.0
+    }
+}
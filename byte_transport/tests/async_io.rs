@@ -0,0 +1,38 @@
+use byte_transport::{AsyncReadMessageExt, AsyncWriteMessageExt, ByteDecode, ByteEncode, DecoderOptions, DEFAULT_MAX_FRAME_SIZE};
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+struct Snapshot {
+    tick: u32,
+    players: Vec<String>,
+    leader: Option<u8>,
+}
+
+#[tokio::test]
+async fn async_messages() -> Result<(), byte_transport::Error> {
+    let (mut client, mut server) = tokio::io::duplex(16);
+    let snapshot = Snapshot { tick: 12, players: vec![String::from("a"), String::from("bb")], leader: Some(1) };
+
+    // The duplex buffer is smaller than the messages, so the writer has to wait on the reader
+    let writer = tokio::spawn(async move {
+        client.write_message(&7u16).await?;
+        client.write_message(&String::from("ready")).await?;
+        client.write_message(&Snapshot { tick: 12, players: vec![String::from("a"), String::from("bb")], leader: Some(1) }).await?;
+        Ok::<(), byte_transport::Error>(())
+    });
+
+    assert_eq!(server.read_message::<u16>().await?, 7u16);
+    assert_eq!(server.read_message::<String>().await?, String::from("ready"));
+    assert_eq!(server.read_message::<Snapshot>().await?, snapshot);
+    writer.await.expect("writer task panicked")?;
+    assert!(matches!(server.read_message::<u8>().await, Err(byte_transport::Error::Io(_))));
+    Ok(())
+}
+
+#[tokio::test]
+async fn async_decoder_options() -> Result<(), byte_transport::Error> {
+    let (mut client, mut server) = tokio::io::duplex(64);
+    client.write_message(&f64::INFINITY).await?;
+    let options = DecoderOptions { finite_floats: true };
+    assert!(matches!(server.read_message_with_options::<f64>(DEFAULT_MAX_FRAME_SIZE, options).await, Err(byte_transport::Error::NonFiniteFloat)));
+    Ok(())
+}
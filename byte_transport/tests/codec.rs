@@ -0,0 +1,82 @@
+use byte_transport::{ByteDecode, ByteEncode, ByteTransportCodec};
+use futures::{SinkExt, StreamExt};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::codec::{Framed, FramedRead};
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+enum Request {
+    Join(String),
+    Move { x: i32, y: i32 },
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+enum Response {
+    Welcome(u32),
+    Moved(i32, i32),
+}
+
+#[tokio::test]
+async fn codec_loopback_tcp() -> Result<(), byte_transport::Error> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?;
+
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await?;
+        let mut framed = Framed::new(stream, ByteTransportCodec::<Request, Response>::new());
+        while let Some(request) = framed.next().await {
+            let response = match request? {
+                Request::Join(name) => Response::Welcome(name.len() as u32),
+                Request::Move { x, y } => Response::Moved(x, y),
+            };
+            framed.send(response).await?;
+        }
+        Ok::<(), byte_transport::Error>(())
+    });
+
+    let stream = TcpStream::connect(address).await?;
+    let mut client = Framed::new(stream, ByteTransportCodec::<Response, Request>::new());
+    client.send(Request::Join(String::from("ferris"))).await?;
+    assert_eq!(client.next().await.transpose()?, Some(Response::Welcome(6)));
+    client.send(Request::Move { x: -3, y: 4 }).await?;
+    assert_eq!(client.next().await.transpose()?, Some(Response::Moved(-3, 4)));
+
+    drop(client);
+    server.await.expect("server task panicked")?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn codec_partial_frames() -> Result<(), byte_transport::Error> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?;
+
+    // The frames are written one byte at a time, so the codec sees every possible split
+    let writer = tokio::spawn(async move {
+        let mut stream = TcpStream::connect(address).await?;
+        let frames = byte_transport::FrameWriter::new();
+        let mut bytes: Vec<u8> = Vec::new();
+        frames.write(&Response::Welcome(1), &mut bytes)?;
+        frames.write(&Response::Moved(7, 8), &mut bytes)?;
+        for byte in bytes {
+            stream.write_all(&[byte]).await?;
+            stream.flush().await?;
+        }
+        Ok::<(), byte_transport::Error>(())
+    });
+
+    let (stream, _) = listener.accept().await?;
+    let mut reader = FramedRead::new(stream, ByteTransportCodec::<Response, Request>::new());
+    assert_eq!(reader.next().await.transpose()?, Some(Response::Welcome(1)));
+    assert_eq!(reader.next().await.transpose()?, Some(Response::Moved(7, 8)));
+    assert!(reader.next().await.is_none());
+
+    writer.await.expect("writer task panicked")?;
+
+    let mut oversize = FramedRead::new(&[0xffu8, 0xff, 0xff, 0x00][..], ByteTransportCodec::<Response, Request>::with_max_frame_size(64));
+    match oversize.next().await {
+        Some(Err(byte_transport::Error::FrameTooLarge { size: 0x00ff_ffff, max: 64 })) => {},
+        result => panic!("Expected frame too large, got {:?}", result),
+    }
+    Ok(())
+}
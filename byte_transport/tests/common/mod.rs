@@ -0,0 +1,133 @@
+// Network shims shared by the packet level tests
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use byte_transport::{Error, ReliableConfig, Transport, UdpTransport};
+
+/// Resends within a couple of milliseconds so the tests don't wait on timers
+pub fn fast() -> ReliableConfig {
+    ReliableConfig { initial_rtt: Duration::from_millis(1), min_resend_delay: Duration::from_millis(1), ..ReliableConfig::default() }
+}
+
+/// Loses, repeats and reorders outgoing packets, driven by a fixed seed so every run sees the same network
+pub struct Lossy<T> {
+    inner: T,
+    state: u64,
+    drop_percent: u64,
+    duplicate_percent: u64,
+    held: Option<Vec<u8>>,
+}
+
+impl<T: Transport> Lossy<T> {
+    pub fn new(inner: T, seed: u64, drop_percent: u64, duplicate_percent: u64) -> Self {
+        Lossy { inner, state: seed, drop_percent, duplicate_percent, held: None }
+    }
+
+    fn roll(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state % 100
+    }
+}
+
+impl<T: Transport> Transport for Lossy<T> {
+    fn send_bytes(&mut self, payload: &[u8]) -> Result<(), Error> {
+        if self.roll() < self.drop_percent {
+            return Ok(());
+        }
+        if self.roll() < self.duplicate_percent {
+            self.inner.send_bytes(payload)?;
+        }
+        // Every other packet waits behind the next one
+        match self.held.take() {
+            Some(held) => {
+                self.inner.send_bytes(payload)?;
+                self.inner.send_bytes(&held)
+            },
+            None if self.roll() < 50 => {
+                self.held = Some(payload.to_vec());
+                Ok(())
+            },
+            None => self.inner.send_bytes(payload),
+        }
+    }
+
+    fn recv_bytes(&mut self) -> Result<Vec<u8>, Error> {
+        self.inner.recv_bytes()
+    }
+
+    fn try_recv_bytes(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.try_recv_bytes()
+    }
+}
+
+/// Two transports on loopback talking to each other. The read timeout only matters to
+/// blocking receives, so a lost datagram fails a test instead of hanging it.
+pub fn udp_pair() -> (UdpTransport, UdpTransport) {
+    let left = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let right = UdpSocket::bind("127.0.0.1:0").unwrap();
+    for socket in [&left, &right] {
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    }
+    let (left_address, right_address) = (left.local_addr().unwrap(), right.local_addr().unwrap());
+    (UdpTransport::new(left, right_address), UdpTransport::new(right, left_address))
+}
+
+/// What `Scripted` does with each outgoing packet, in order
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Fate {
+    Deliver,
+    Drop,
+    /// Sent right after this many later packets have been delivered
+    Delay(usize),
+}
+
+/// Applies a fixed list of fates to outgoing packets, delivering everything once the list runs out
+pub struct Scripted<T> {
+    inner: T,
+    fates: VecDeque<Fate>,
+    delayed: Vec<(usize, Vec<u8>)>,
+    /// Every outgoing packet, whatever became of it
+    pub sent: Vec<Vec<u8>>,
+}
+
+impl<T: Transport> Scripted<T> {
+    pub fn new(inner: T, fates: &[Fate]) -> Self {
+        Scripted { inner, fates: fates.iter().copied().collect(), delayed: Vec::new(), sent: Vec::new() }
+    }
+}
+
+impl<T: Transport> Transport for Scripted<T> {
+    fn send_bytes(&mut self, payload: &[u8]) -> Result<(), Error> {
+        self.sent.push(payload.to_vec());
+        match self.fates.pop_front().unwrap_or(Fate::Deliver) {
+            Fate::Drop => Ok(()),
+            Fate::Delay(packets) => {
+                self.delayed.push((packets, payload.to_vec()));
+                Ok(())
+            },
+            Fate::Deliver => {
+                self.inner.send_bytes(payload)?;
+                for (packets, delayed) in std::mem::take(&mut self.delayed) {
+                    match packets - 1 {
+                        0 => self.inner.send_bytes(&delayed)?,
+                        packets => self.delayed.push((packets, delayed)),
+                    }
+                }
+                Ok(())
+            },
+        }
+    }
+
+    fn recv_bytes(&mut self) -> Result<Vec<u8>, Error> {
+        self.inner.recv_bytes()
+    }
+
+    fn try_recv_bytes(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.try_recv_bytes()
+    }
+}
@@ -0,0 +1,5 @@
+#[test]
+fn derive_diagnostics() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,189 @@
+mod common;
+
+use std::collections::BTreeSet;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use byte_transport::{ByteDecode, ByteEncode, Connection, DecoderOptions, Delivery, Error, MemoryTransport, ReliableConfig, Transport};
+use common::{fast, udp_pair, Fate, Lossy, Scripted};
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+struct Chat {
+    line: u32,
+    text: String,
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+struct Position {
+    tick: u32,
+    xyz: [f32; 3],
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+struct Event {
+    id: u32,
+}
+
+fn game_connection<T: Transport>(transport: T, config: ReliableConfig) -> Connection<T> {
+    Connection::with_config(transport, config)
+        .with_channel("chat", Delivery::ReliableOrdered)
+        .and_then(|connection| connection.with_channel("positions", Delivery::UnreliableSequenced))
+        .and_then(|connection| connection.with_channel("events", Delivery::ReliableUnordered))
+        .unwrap()
+}
+
+fn chat(line: u32) -> Chat {
+    Chat { line, text: format!("line {line}") }
+}
+
+fn position(tick: u32) -> Position {
+    Position { tick, xyz: [tick as f32, 0.0, 1.0] }
+}
+
+#[test]
+fn channels_over_lossy_udp() {
+    let (left, right) = udp_pair();
+    let mut sender = game_connection(Lossy::new(left, 0x853c_49e6_748f_ea9b, 25, 10), fast());
+    let mut receiver = game_connection(Lossy::new(right, 0xda3e_39cb_94b9_5bdb, 25, 10), fast());
+
+    let (mut chats, mut positions, mut events) = (Vec::new(), Vec::new(), BTreeSet::new());
+    let started = Instant::now();
+    for tick in 0.. {
+        assert!(started.elapsed() < Duration::from_secs(20), "stalled with {} chats and {} events", chats.len(), events.len());
+        if tick < 300 {
+            sender.send("chat", &chat(tick)).unwrap();
+            sender.send("positions", &position(tick)).unwrap();
+            sender.send("events", &Event { id: tick }).unwrap();
+        } else if sender.in_flight() == 0 {
+            break;
+        }
+        sender.update().unwrap();
+        receiver.update().unwrap();
+        while let Some(chat) = receiver.recv::<Chat>("chat").unwrap() {
+            chats.push(chat);
+        }
+        while let Some(position) = receiver.recv::<Position>("positions").unwrap() {
+            positions.push(position.tick);
+        }
+        while let Some(event) = receiver.recv::<Event>("events").unwrap() {
+            assert!(events.insert(event.id), "event {} delivered twice", event.id);
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    receiver.update().unwrap();
+    while let Some(event) = receiver.recv::<Event>("events").unwrap() {
+        assert!(events.insert(event.id));
+    }
+    while let Some(chat) = receiver.recv::<Chat>("chat").unwrap() {
+        chats.push(chat);
+    }
+
+    assert_eq!(chats, (0..300).map(chat).collect::<Vec<_>>());
+    assert_eq!(events, (0..300).collect());
+    // Some positions are lost for good, but the rest never go backwards
+    assert!(!positions.is_empty() && positions.len() < 300);
+    assert!(positions.windows(2).all(|pair| pair[0] < pair[1]), "{positions:?}");
+}
+
+#[test]
+fn a_gap_only_holds_up_its_own_channel() {
+    let (left, right) = MemoryTransport::pair();
+    let mut sender = game_connection(Scripted::new(left, &[Fate::Drop]), fast());
+    let mut receiver = game_connection(right, fast());
+
+    sender.send("chat", &chat(0)).unwrap();
+    sender.send("events", &Event { id: 0 }).unwrap();
+    sender.update().unwrap();
+    sender.send("chat", &chat(1)).unwrap();
+    sender.send("events", &Event { id: 1 }).unwrap();
+    sender.send("positions", &position(1)).unwrap();
+    thread::sleep(Duration::from_millis(5));
+    // The lost chat packet is resent along with the new messages
+    sender.update().unwrap();
+    receiver.update().unwrap();
+    assert_eq!(receiver.recv::<Event>("events").unwrap(), Some(Event { id: 0 }));
+    assert_eq!(receiver.recv::<Event>("events").unwrap(), Some(Event { id: 1 }));
+    assert_eq!(receiver.recv::<Position>("positions").unwrap(), Some(position(1)));
+    assert_eq!(receiver.recv::<Chat>("chat").unwrap(), Some(chat(0)));
+    assert_eq!(receiver.recv::<Chat>("chat").unwrap(), Some(chat(1)));
+}
+
+#[test]
+fn unordered_delivery_skips_the_wait() {
+    let (left, right) = MemoryTransport::pair();
+    // Both packets of the first update are lost, with no time for a resend
+    let mut sender = game_connection(Scripted::new(left, &[Fate::Drop, Fate::Drop]), ReliableConfig::default());
+    let mut receiver = game_connection(right, fast());
+
+    for id in 0..2 {
+        sender.send("events", &Event { id }).unwrap();
+        sender.send("chat", &chat(id)).unwrap();
+        sender.update().unwrap();
+    }
+    receiver.update().unwrap();
+    assert_eq!(receiver.recv::<Event>("events").unwrap(), Some(Event { id: 1 }));
+    assert_eq!(receiver.recv::<Chat>("chat").unwrap(), None);
+}
+
+#[test]
+fn stale_positions_are_dropped() {
+    let (left, right) = MemoryTransport::pair();
+    let mut sender = game_connection(Scripted::new(left, &[Fate::Delay(1)]), fast());
+    let mut receiver = game_connection(right, fast());
+
+    for tick in 1..=2 {
+        sender.send("positions", &position(tick)).unwrap();
+        sender.update().unwrap();
+    }
+    // Tick 2 overtook tick 1 on the way
+    receiver.update().unwrap();
+    assert_eq!(receiver.recv::<Position>("positions").unwrap(), Some(position(2)));
+    assert_eq!(receiver.recv::<Position>("positions").unwrap(), None);
+    // Unreliable messages are never resent
+    assert_eq!(sender.in_flight(), 0);
+}
+
+#[test]
+fn channel_id_is_in_the_packet_header() {
+    let (left, mut raw) = MemoryTransport::pair();
+    let mut sender = game_connection(left, fast());
+    assert_eq!(sender.channel_id("events"), Some(2));
+    sender.send("events", &Event { id: 7 }).unwrap();
+    sender.update().unwrap();
+    let packet = raw.recv_bytes().unwrap();
+    assert_eq!(packet[8], 2);
+    // One message with id 0, whole rather than a fragment, four bytes long
+    assert_eq!(&packet[9..], &[1, 0, 0, 0, 0, 1, 4, 0, 7, 0, 0, 0]);
+}
+
+#[test]
+fn decoder_options_apply_to_recv() {
+    let (left, right) = MemoryTransport::pair();
+    let mut sender = game_connection(left, fast());
+    let mut receiver = game_connection(right, fast()).with_decoder_options(DecoderOptions { finite_floats: true });
+    sender.send("positions", &Position { tick: 1, xyz: [0.0, f32::NAN, 0.0] }).unwrap();
+    sender.update().unwrap();
+    receiver.update().unwrap();
+    assert!(matches!(receiver.recv::<Position>("positions"), Err(Error::NonFiniteFloat)));
+}
+
+#[test]
+fn channel_errors() {
+    let (left, mut raw) = MemoryTransport::pair();
+    let mut connection = game_connection(left, fast());
+    assert!(matches!(connection.send("voice", &1u8), Err(Error::UnknownChannel(name)) if name == "voice"));
+    assert!(matches!(connection.recv::<u8>("voice"), Err(Error::UnknownChannel(_))));
+    assert!(matches!(connection.add_channel("chat", Delivery::ReliableUnordered), Err(Error::DuplicateChannel(name)) if name == "chat"));
+    assert_eq!(connection.add_channel("voice", Delivery::UnreliableSequenced).unwrap(), 3);
+
+    raw.send_bytes(&[0, 0, 0, 0, 0, 0, 0, 0, 9, 1, 0, 0, 0, 0, 1, 0, 0]).unwrap();
+    connection.update().unwrap();
+    assert_eq!(connection.dropped_packets(), 1);
+    assert!(matches!(connection.last_packet_error(), Some(Error::UnknownChannelId(9))));
+
+    let mut full = Connection::new(MemoryTransport::pair().0);
+    for id in 0..256 {
+        full.add_channel(format!("channel {id}"), Delivery::ReliableOrdered).unwrap();
+    }
+    assert!(matches!(full.add_channel("one more", Delivery::ReliableOrdered), Err(Error::TooManyChannels { max: 256 })));
+}
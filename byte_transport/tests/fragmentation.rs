@@ -0,0 +1,236 @@
+mod common;
+
+use std::collections::BTreeSet;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use byte_transport::{
+    ByteDecode, ByteEncode, Connection, Delivery, Error, MemoryTransport, ReliableChannel, ReliableConfig, Transport, PACKET_HEADER_LEN,
+};
+use common::{fast, udp_pair, Fate, Lossy, Scripted};
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug, Clone)]
+struct Transform {
+    translation: [f32; 3],
+    rotation: [f32; 4],
+    scale: [f32; 3],
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug, Clone)]
+struct Snapshot {
+    tick: u32,
+    transforms: Vec<Transform>,
+}
+
+// 40 bytes per transform, so a few hundred entities need several packets
+fn snapshot(tick: u32, entities: usize) -> Snapshot {
+    let transforms = (0..entities)
+        .map(|entity| {
+            let value = (tick as usize * 1000 + entity) as f32;
+            Transform { translation: [value, -value, 0.5], rotation: [0.0, 0.0, 0.0, 1.0], scale: [1.0; 3] }
+        })
+        .collect();
+    Snapshot { tick, transforms }
+}
+
+#[test]
+fn large_snapshots_over_lossy_udp() {
+    let (left, right) = udp_pair();
+    let mut sender = ReliableChannel::with_config(Lossy::new(left, 0x5851_f42d_4c95_7f2d, 20, 5), fast());
+    let mut receiver = ReliableChannel::with_config(Lossy::new(right, 0x1405_7b7e_f767_814f, 20, 5), fast());
+
+    let sent: Vec<_> = (0..20).map(|tick| snapshot(tick, 30 + tick as usize * 20)).collect();
+    for snapshot in &sent {
+        sender.send(snapshot).unwrap();
+    }
+    let mut received = Vec::new();
+    let started = Instant::now();
+    while received.len() < sent.len() || sender.in_flight() > 0 {
+        assert!(started.elapsed() < Duration::from_secs(20), "stalled after {} snapshots", received.len());
+        sender.update().unwrap();
+        receiver.update().unwrap();
+        while let Some(snapshot) = receiver.recv::<Snapshot>().unwrap() {
+            received.push(snapshot);
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(received, sent);
+}
+
+#[test]
+fn lost_fragments_are_resent_alone() {
+    let (left, right) = MemoryTransport::pair();
+    let mut sender = ReliableChannel::with_config(Scripted::new(left, &[Fate::Deliver, Fate::Deliver, Fate::Drop]), fast());
+    let mut receiver = ReliableChannel::with_config(right, fast());
+
+    let message = snapshot(1, 120);
+    sender.send(&message).unwrap();
+    sender.update().unwrap();
+    let first_round = sender.get_ref().sent.len();
+    assert_eq!(first_round, 5);
+    assert!(sender.get_ref().sent.iter().all(|packet| packet.len() <= 1200));
+
+    // Everything but the third fragment arrived, which is all the receiver acks
+    receiver.update().unwrap();
+    assert_eq!(receiver.recv::<Snapshot>().unwrap(), None);
+    sender.update().unwrap();
+    assert_eq!(sender.in_flight(), 1);
+    thread::sleep(Duration::from_millis(5));
+    sender.update().unwrap();
+    let resent = &sender.get_ref().sent[first_round..];
+    assert!(!resent.is_empty());
+    for packet in resent {
+        // One message, fragment 2 of 5
+        assert_eq!(&packet[PACKET_HEADER_LEN - 2..PACKET_HEADER_LEN], &[1, 0]);
+        assert_eq!(&packet[PACKET_HEADER_LEN..PACKET_HEADER_LEN + 4], &[2, 0, 2, 5]);
+    }
+
+    receiver.update().unwrap();
+    assert_eq!(receiver.recv::<Snapshot>().unwrap(), Some(message));
+}
+
+#[test]
+fn unordered_fragments_out_of_order() {
+    let (left, right) = MemoryTransport::pair();
+    let config = ReliableConfig { max_packet_size: 300, ..fast() };
+    let mut sender = Connection::with_config(Lossy::new(left, 99, 30, 30), config).with_channel("events", Delivery::ReliableUnordered).unwrap();
+    let mut receiver = Connection::with_config(right, config).with_channel("events", Delivery::ReliableUnordered).unwrap();
+
+    for tick in 0..40 {
+        sender.send("events", &snapshot(tick, 1 + tick as usize % 12)).unwrap();
+    }
+    let mut received = BTreeSet::new();
+    let started = Instant::now();
+    while received.len() < 40 || sender.in_flight() > 0 {
+        assert!(started.elapsed() < Duration::from_secs(20), "stalled after {} snapshots", received.len());
+        sender.update().unwrap();
+        receiver.update().unwrap();
+        while let Some(event) = receiver.recv::<Snapshot>("events").unwrap() {
+            assert_eq!(event, snapshot(event.tick, 1 + event.tick as usize % 12));
+            assert!(received.insert(event.tick), "snapshot {} delivered twice", event.tick);
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(receiver.reassembly_bytes(), 0);
+}
+
+fn sequenced_pair(fates: &[Fate], config: ReliableConfig) -> (Connection<Scripted<MemoryTransport>>, Connection<MemoryTransport>) {
+    let (left, right) = MemoryTransport::pair();
+    let sender = Connection::with_config(Scripted::new(left, fates), config).with_channel("snapshots", Delivery::UnreliableSequenced).unwrap();
+    let receiver = Connection::with_config(right, config).with_channel("snapshots", Delivery::UnreliableSequenced).unwrap();
+    (sender, receiver)
+}
+
+#[test]
+fn incomplete_messages_time_out() {
+    let config = ReliableConfig { reassembly_timeout: Duration::from_millis(10), ..ReliableConfig::default() };
+    let (mut sender, mut receiver) = sequenced_pair(&[Fate::Deliver, Fate::Drop], config);
+
+    sender.send("snapshots", &snapshot(1, 100)).unwrap();
+    sender.update().unwrap();
+    receiver.update().unwrap();
+    assert!(receiver.reassembly_bytes() > 0);
+    thread::sleep(Duration::from_millis(20));
+    receiver.update().unwrap();
+    assert_eq!(receiver.reassembly_bytes(), 0);
+
+    // Unreliable fragments are never resent, the next snapshot gets through whole
+    sender.send("snapshots", &snapshot(2, 100)).unwrap();
+    sender.update().unwrap();
+    receiver.update().unwrap();
+    assert_eq!(receiver.recv::<Snapshot>("snapshots").unwrap(), Some(snapshot(2, 100)));
+    assert_eq!(receiver.recv::<Snapshot>("snapshots").unwrap(), None);
+}
+
+#[test]
+fn newer_snapshots_discard_older_fragments() {
+    // The last fragment of the first snapshot shows up after the whole second one
+    let (mut sender, mut receiver) = sequenced_pair(&[Fate::Deliver, Fate::Delay(2)], ReliableConfig::default());
+    sender.send("snapshots", &snapshot(1, 40)).unwrap();
+    sender.send("snapshots", &snapshot(2, 40)).unwrap();
+    sender.update().unwrap();
+    receiver.update().unwrap();
+    assert_eq!(receiver.recv::<Snapshot>("snapshots").unwrap(), Some(snapshot(2, 40)));
+    assert_eq!(receiver.recv::<Snapshot>("snapshots").unwrap(), None);
+    assert_eq!(receiver.reassembly_bytes(), 0);
+}
+
+#[test]
+fn reassembly_buffer_is_bounded() {
+    let config = ReliableConfig { max_reassembly_bytes: 3000, ..ReliableConfig::default() };
+    let mut fates = vec![Fate::Deliver; 4];
+    fates.push(Fate::Drop);
+    fates.extend([Fate::Deliver; 4]);
+    fates.push(Fate::Drop);
+    let (mut sender, mut receiver) = sequenced_pair(&fates, config);
+
+    // Two snapshots of five fragments, each missing its last one
+    sender.send("snapshots", &snapshot(1, 140)).unwrap();
+    sender.send("snapshots", &snapshot(2, 140)).unwrap();
+    sender.update().unwrap();
+    receiver.update().unwrap();
+    assert!(receiver.reassembly_bytes() <= 3000, "{}", receiver.reassembly_bytes());
+    assert_eq!(receiver.recv::<Snapshot>("snapshots").unwrap(), None);
+}
+
+#[test]
+fn reliable_channels_hold_back_instead_of_dropping() {
+    // Timing out or evicting anything here would lose messages for good
+    let config = ReliableConfig { max_reassembly_bytes: 3000, reassembly_timeout: Duration::from_millis(1), ..fast() };
+    for delivery in [Delivery::ReliableOrdered, Delivery::ReliableUnordered] {
+        let (left, right) = MemoryTransport::pair();
+        let mut sender = Connection::with_config(Scripted::new(left, &[Fate::Drop]), config).with_channel("world", delivery).unwrap();
+        let mut receiver = Connection::with_config(right, config).with_channel("world", delivery).unwrap();
+        let limit = config.max_reassembly_bytes + 3 * sender.fragment_size();
+
+        // Three fragments each, and the very first one is lost
+        let sent: Vec<_> = (0..6).map(|tick| snapshot(tick, 70)).collect();
+        for snapshot in &sent {
+            sender.send("world", snapshot).unwrap();
+        }
+        sender.update().unwrap();
+        receiver.update().unwrap();
+        assert!(receiver.reassembly_bytes() <= config.max_reassembly_bytes, "{}", receiver.reassembly_bytes());
+
+        let mut received = Vec::new();
+        let started = Instant::now();
+        while received.len() < sent.len() || sender.in_flight() > 0 {
+            assert!(started.elapsed() < Duration::from_secs(20), "{delivery:?} stalled after {} snapshots", received.len());
+            thread::sleep(Duration::from_millis(2));
+            sender.update().unwrap();
+            receiver.update().unwrap();
+            assert!(receiver.reassembly_bytes() <= limit, "{}", receiver.reassembly_bytes());
+            while let Some(snapshot) = receiver.recv::<Snapshot>("world").unwrap() {
+                received.push(snapshot);
+            }
+        }
+        received.sort_by_key(|snapshot| snapshot.tick);
+        assert_eq!(received, sent, "{delivery:?}");
+        assert_eq!(receiver.reassembly_bytes(), 0);
+    }
+}
+
+#[test]
+fn fragment_count_limits() {
+    let config = ReliableConfig { max_fragments: 4, ..ReliableConfig::default() };
+    let (mut sender, mut receiver) = sequenced_pair(&[], config);
+    assert_eq!(sender.fragment_size(), 1200 - PACKET_HEADER_LEN - byte_transport::MESSAGE_HEADER_LEN);
+    assert!(matches!(sender.send_bytes("snapshots", vec![0; 4 * 1183 + 1]), Err(Error::FrameTooLarge { size: 4733, max: 4732 })));
+    sender.send_bytes("snapshots", vec![0; 4 * 1183]).unwrap();
+
+    // A packet claiming a message of 200 fragments is refused before anything is kept
+    let (mut raw, channel) = MemoryTransport::pair();
+    let mut channel = Connection::with_config(channel, config).with_channel("snapshots", Delivery::UnreliableSequenced).unwrap();
+    raw.send_bytes(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 200, 1, 0, 7]).unwrap();
+    channel.update().unwrap();
+    assert!(matches!(channel.last_packet_error(), Some(Error::TooManyFragments { count: 200, max: 4 })));
+    raw.send_bytes(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 3, 3, 1, 0, 7]).unwrap();
+    channel.update().unwrap();
+    assert!(matches!(channel.last_packet_error(), Some(Error::SimpleDecodeError(_))));
+    assert_eq!(channel.dropped_packets(), 2);
+    assert_eq!(channel.reassembly_bytes(), 0);
+
+    sender.update().unwrap();
+    receiver.update().unwrap();
+    assert_eq!(receiver.recv_bytes("snapshots").unwrap(), Some(vec![0; 4 * 1183]));
+}
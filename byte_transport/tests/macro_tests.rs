@@ -1,4 +1,4 @@
-use byte_transport::{ByteDecode, ByteEncode, Decoder};
+use byte_transport::{ByteDecode, ByteEncode, Checksum, Decoder};
 
 #[derive(ByteEncode, ByteDecode, PartialEq, Eq, Debug, Default)]
 struct TestStruct {
@@ -67,3 +67,982 @@ fn macro_enum_test() -> Result<(), byte_transport::Error> {
 
     Ok(())
 }
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Eq, Debug)]
+struct EntityId(u32);
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Eq, Debug)]
+struct Pair(i16, Option<bool>);
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Eq, Debug)]
+struct Ping;
+
+#[test]
+fn macro_tuple_struct_test() -> Result<(), byte_transport::Error> {
+    let entity_id = EntityId(42u32);
+    let mut bytes: Vec<u8> = Vec::new();
+    entity_id.simple_encode(&mut bytes)?;
+    assert_eq!(bytes, 42u32.to_le_bytes());
+    let decoded_entity_id = EntityId::simple_decode(&mut Decoder::new(bytes))?;
+    assert_eq!(entity_id, decoded_entity_id);
+
+    let pair = Pair(-3i16, Some(true));
+    let mut pair_bytes: Vec<u8> = Vec::new();
+    pair.simple_encode(&mut pair_bytes)?;
+    let decoded_pair = Pair::simple_decode(&mut Decoder::new(pair_bytes))?;
+    assert_eq!(pair, decoded_pair);
+
+    Ok(())
+}
+
+#[test]
+fn macro_unit_struct_test() -> Result<(), byte_transport::Error> {
+    let mut bytes: Vec<u8> = Vec::new();
+    Ping.simple_encode(&mut bytes)?;
+    assert!(bytes.is_empty());
+    let decoded_ping = Ping::simple_decode(&mut Decoder::new(bytes))?;
+    assert_eq!(Ping, decoded_ping);
+
+    Ok(())
+}
+
+fn default_cache_size() -> usize {
+    64
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Eq, Debug)]
+struct CachedState {
+    id: u32,
+    #[ig(default = "default_cache_size")]
+    cache_size: usize,
+    #[ig]
+    handle: Option<u64>,
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Eq, Debug)]
+enum SkipEnum {
+    A(#[ig] u64, i32),
+    B {
+        #[ig]
+        local: bool,
+        remote: i16,
+    },
+}
+
+#[test]
+fn macro_ignore_test() -> Result<(), byte_transport::Error> {
+    let sub_struct = SubStruct {
+        b: true,
+        integer_32: 12i32,
+    };
+    let mut bytes: Vec<u8> = Vec::new();
+    sub_struct.simple_encode(&mut bytes)?;
+    assert_eq!(bytes, vec![1u8]);
+    let decoded_sub_struct = SubStruct::simple_decode(&mut Decoder::new(bytes))?;
+    assert_eq!(decoded_sub_struct, SubStruct { b: true, integer_32: 0i32 });
+
+    let cached_state = CachedState {
+        id: 7u32,
+        cache_size: 1usize,
+        handle: Some(99u64),
+    };
+    let mut cached_bytes: Vec<u8> = Vec::new();
+    cached_state.simple_encode(&mut cached_bytes)?;
+    assert_eq!(cached_bytes.len(), 4);
+    let decoded_cached_state = CachedState::simple_decode(&mut Decoder::new(cached_bytes))?;
+    assert_eq!(decoded_cached_state, CachedState { id: 7u32, cache_size: 64usize, handle: None });
+
+    let skip_a = SkipEnum::A(5u64, -1i32);
+    let mut bytes_a: Vec<u8> = Vec::new();
+    skip_a.simple_encode(&mut bytes_a)?;
+    assert_eq!(bytes_a.len(), 5);
+    assert_eq!(SkipEnum::simple_decode(&mut Decoder::new(bytes_a))?, SkipEnum::A(0u64, -1i32));
+
+    let skip_b = SkipEnum::B { local: true, remote: 3i16 };
+    let mut bytes_b: Vec<u8> = Vec::new();
+    skip_b.simple_encode(&mut bytes_b)?;
+    assert_eq!(bytes_b.len(), 3);
+    assert_eq!(SkipEnum::simple_decode(&mut Decoder::new(bytes_b))?, SkipEnum::B { local: false, remote: 3i16 });
+
+    Ok(())
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Eq, Debug, Clone, Copy)]
+enum Discriminants {
+    First = 5,
+    Second,
+    #[bt(tag = 40)]
+    Third,
+    Fourth = 2,
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Eq, Debug)]
+#[bt(tag_type = u16)]
+enum WideTags {
+    #[bt(tag = 1000)]
+    A(u8),
+    B { value: i32 },
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Eq, Debug)]
+#[bt(tag_type = varint)]
+enum VarIntTags {
+    Small,
+    #[bt(tag = 70000)]
+    Large(bool),
+}
+
+#[test]
+fn macro_enum_tag_test() -> Result<(), byte_transport::Error> {
+    for (variant, tag) in [
+        (Discriminants::First, 5u8),
+        (Discriminants::Second, 6u8),
+        (Discriminants::Third, 40u8),
+        (Discriminants::Fourth, 2u8),
+    ] {
+        let mut bytes: Vec<u8> = Vec::new();
+        variant.simple_encode(&mut bytes)?;
+        assert_eq!(bytes, vec![tag]);
+        assert_eq!(Discriminants::simple_decode(&mut Decoder::new(bytes))?, variant);
+    }
+
+    let wide_a = WideTags::A(3u8);
+    let mut bytes_a: Vec<u8> = Vec::new();
+    wide_a.simple_encode(&mut bytes_a)?;
+    assert_eq!(bytes_a, vec![0xe8, 0x03, 3u8]);
+    assert_eq!(WideTags::simple_decode(&mut Decoder::new(bytes_a))?, wide_a);
+
+    let wide_b = WideTags::B { value: -9i32 };
+    let mut bytes_b: Vec<u8> = Vec::new();
+    wide_b.simple_encode(&mut bytes_b)?;
+    assert_eq!(&bytes_b[..2], &1001u16.to_le_bytes());
+    assert_eq!(WideTags::simple_decode(&mut Decoder::new(bytes_b))?, wide_b);
+
+    let mut small_bytes: Vec<u8> = Vec::new();
+    VarIntTags::Small.simple_encode(&mut small_bytes)?;
+    assert_eq!(small_bytes, vec![0u8]);
+    assert_eq!(VarIntTags::simple_decode(&mut Decoder::new(small_bytes))?, VarIntTags::Small);
+
+    let large = VarIntTags::Large(true);
+    let mut large_bytes: Vec<u8> = Vec::new();
+    large.simple_encode(&mut large_bytes)?;
+    assert_eq!(large_bytes.len(), 4);
+    assert_eq!(VarIntTags::simple_decode(&mut Decoder::new(large_bytes))?, large);
+
+    match WideTags::simple_decode(&mut Decoder::new(vec![0x10, 0x27])) {
+        Err(byte_transport::Error::DecodingEnumVariant(10000)) => {},
+        result => panic!("Expected unknown variant error, got {:?}", result),
+    }
+
+    Ok(())
+}
+
+mod ipv4_codec {
+    use std::net::Ipv4Addr;
+    use byte_transport::{ByteDecode, ByteEncode, Decoder, Error};
+
+    pub fn simple_encode(addr: &Ipv4Addr, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        addr.octets().simple_encode(bytes)
+    }
+
+    pub fn simple_decode(decoder: &mut Decoder) -> Result<Ipv4Addr, Error> {
+        Ok(Ipv4Addr::from(<[u8; 4]>::simple_decode(decoder)?))
+    }
+}
+
+fn encode_millis(duration: &std::time::Duration, bytes: &mut Vec<u8>) -> Result<(), byte_transport::Error> {
+    (duration.as_millis() as u64).simple_encode(bytes)
+}
+
+fn decode_millis(decoder: &mut Decoder) -> Result<std::time::Duration, byte_transport::Error> {
+    Ok(std::time::Duration::from_millis(u64::simple_decode(decoder)?))
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Eq, Debug)]
+struct Peer {
+    #[bt(with = "ipv4_codec")]
+    addr: std::net::Ipv4Addr,
+    #[bt(encode_with = "encode_millis", decode_with = "decode_millis")]
+    latency: std::time::Duration,
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Eq, Debug)]
+enum PeerEvent {
+    Joined(#[bt(with = "ipv4_codec")] std::net::Ipv4Addr, u16),
+    Timeout {
+        #[bt(encode_with = "encode_millis", decode_with = "decode_millis")]
+        after: std::time::Duration,
+    },
+}
+
+#[test]
+fn macro_with_test() -> Result<(), byte_transport::Error> {
+    let peer = Peer {
+        addr: std::net::Ipv4Addr::new(10, 0, 0, 1),
+        latency: std::time::Duration::from_millis(250),
+    };
+    let mut bytes: Vec<u8> = Vec::new();
+    peer.simple_encode(&mut bytes)?;
+    assert_eq!(bytes.len(), 12);
+    assert_eq!(&bytes[..4], &[10u8, 0, 0, 1]);
+    assert_eq!(Peer::simple_decode(&mut Decoder::new(bytes))?, peer);
+
+    let joined = PeerEvent::Joined(std::net::Ipv4Addr::LOCALHOST, 8080u16);
+    let mut joined_bytes: Vec<u8> = Vec::new();
+    joined.simple_encode(&mut joined_bytes)?;
+    assert_eq!(PeerEvent::simple_decode(&mut Decoder::new(joined_bytes))?, joined);
+
+    let timeout = PeerEvent::Timeout { after: std::time::Duration::from_millis(1500) };
+    let mut timeout_bytes: Vec<u8> = Vec::new();
+    timeout.simple_encode(&mut timeout_bytes)?;
+    assert_eq!(PeerEvent::simple_decode(&mut Decoder::new(timeout_bytes))?, timeout);
+
+    Ok(())
+}
+
+mod facade {
+    pub use ::byte_transport as wire;
+}
+
+mod no_imports {
+    #[derive(byte_transport::ByteEncode, byte_transport::ByteDecode, PartialEq, Eq, Debug)]
+    pub struct Position(pub i32, pub i32);
+
+    #[derive(byte_transport::ByteEncode, byte_transport::ByteDecode, PartialEq, Eq, Debug)]
+    #[bt(tag_type = varint)]
+    pub enum Command {
+        Stop,
+        Move { to: Position },
+    }
+
+    #[derive(crate::facade::wire::ByteEncode, crate::facade::wire::ByteDecode, PartialEq, Eq, Debug)]
+    #[bt(crate = "crate::facade::wire")]
+    pub struct Renamed {
+        pub id: u16,
+        pub command: Command,
+    }
+}
+
+#[test]
+fn macro_hygiene_test() -> Result<(), byte_transport::Error> {
+    let renamed = no_imports::Renamed {
+        id: 4u16,
+        command: no_imports::Command::Move { to: no_imports::Position(-1i32, 8i32) },
+    };
+    let mut bytes: Vec<u8> = Vec::new();
+    renamed.simple_encode(&mut bytes)?;
+    assert_eq!(no_imports::Renamed::simple_decode(&mut Decoder::new(bytes))?, renamed);
+
+    Ok(())
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Eq, Debug)]
+struct LoginV1 {
+    user_id: u32,
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Eq, Debug)]
+struct LoginV2 {
+    user_id: u32,
+    #[bt(default_on_eof)]
+    client_version: u16,
+    #[bt(default_on_eof)]
+    locale: Option<String>,
+}
+
+#[test]
+fn macro_default_on_eof_test() -> Result<(), byte_transport::Error> {
+    let login_v1 = LoginV1 { user_id: 77u32 };
+    let mut v1_bytes: Vec<u8> = Vec::new();
+    login_v1.simple_encode(&mut v1_bytes)?;
+    let upgraded = LoginV2::simple_decode(&mut Decoder::new(v1_bytes))?;
+    assert_eq!(upgraded, LoginV2 { user_id: 77u32, client_version: 0u16, locale: None });
+
+    let login_v2 = LoginV2 {
+        user_id: 77u32,
+        client_version: 3u16,
+        locale: Some("en".to_string()),
+    };
+    let mut v2_bytes: Vec<u8> = Vec::new();
+    login_v2.simple_encode(&mut v2_bytes)?;
+    assert_eq!(LoginV2::simple_decode(&mut Decoder::new(v2_bytes.clone()))?, login_v2);
+
+    // Cut inside `client_version`, which must not be mistaken for a clean end
+    match LoginV2::simple_decode(&mut Decoder::new(v2_bytes[..5].to_vec())) {
+        Err(byte_transport::Error::UnexpectedEof { needed: 2, remaining: 1 }) => {},
+        result => panic!("Expected truncation error, got {:?}", result),
+    }
+
+    Ok(())
+}
+
+fn check_health(stats: &PlayerStats) -> Result<(), byte_transport::Error> {
+    if stats.health > stats.max_health {
+        return Err(byte_transport::Error::Validation {
+            field: "health".to_string(),
+            reason: "health exceeds max_health".to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Eq, Debug)]
+#[bt(validate = "check_health")]
+struct PlayerStats {
+    health: u16,
+    max_health: u16,
+    #[bt(range = 0..=100)]
+    armor: u8,
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Eq, Debug)]
+enum Purchase {
+    Item(u32, #[bt(range = 1..100)] u16),
+    Gold {
+        #[bt(range = ..10_000)]
+        amount: u32,
+    },
+}
+
+fn expect_validation<T: std::fmt::Debug>(result: Result<T, byte_transport::Error>, expected_field: &str) {
+    match result {
+        Err(byte_transport::Error::Validation { field, .. }) => assert_eq!(field, expected_field),
+        result => panic!("Expected validation error for {}, got {:?}", expected_field, result),
+    }
+}
+
+#[test]
+fn macro_validation_test() -> Result<(), byte_transport::Error> {
+    let stats = PlayerStats { health: 80u16, max_health: 100u16, armor: 100u8 };
+    let mut bytes: Vec<u8> = Vec::new();
+    stats.simple_encode(&mut bytes)?;
+    assert_eq!(PlayerStats::simple_decode(&mut Decoder::new(bytes))?, stats);
+
+    let overhealed = PlayerStats { health: 120u16, max_health: 100u16, armor: 5u8 };
+    let mut overhealed_bytes: Vec<u8> = Vec::new();
+    overhealed.simple_encode(&mut overhealed_bytes)?;
+    expect_validation(PlayerStats::simple_decode(&mut Decoder::new(overhealed_bytes)), "health");
+
+    let overarmored = PlayerStats { health: 1u16, max_health: 100u16, armor: 101u8 };
+    let mut overarmored_bytes: Vec<u8> = Vec::new();
+    overarmored.simple_encode(&mut overarmored_bytes)?;
+    expect_validation(PlayerStats::simple_decode(&mut Decoder::new(overarmored_bytes)), "armor");
+
+    let item = Purchase::Item(9u32, 0u16);
+    let mut item_bytes: Vec<u8> = Vec::new();
+    item.simple_encode(&mut item_bytes)?;
+    expect_validation(Purchase::simple_decode(&mut Decoder::new(item_bytes)), "1");
+
+    let gold = Purchase::Gold { amount: 9_999u32 };
+    let mut gold_bytes: Vec<u8> = Vec::new();
+    gold.simple_encode(&mut gold_bytes)?;
+    assert_eq!(Purchase::simple_decode(&mut Decoder::new(gold_bytes))?, gold);
+
+    let too_much_gold = Purchase::Gold { amount: 10_000u32 };
+    let mut too_much_bytes: Vec<u8> = Vec::new();
+    too_much_gold.simple_encode(&mut too_much_bytes)?;
+    expect_validation(Purchase::simple_decode(&mut Decoder::new(too_much_bytes)), "amount");
+
+    Ok(())
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+struct ClientMove {
+    #[bt(finite)]
+    position: [f32; 3],
+    #[bt(canonical_nan)]
+    hint: f64,
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+#[bt(finite)]
+enum PhysicsInput {
+    Impulse(Vec<f32>),
+    Spin { radians: Option<f64> },
+}
+
+#[test]
+fn macro_finite_float_test() -> Result<(), byte_transport::Error> {
+    let client_move = ClientMove { position: [1f32, 2f32, 3f32], hint: 0.5f64 };
+    let mut bytes: Vec<u8> = Vec::new();
+    client_move.simple_encode(&mut bytes)?;
+    assert_eq!(ClientMove::simple_decode(&mut Decoder::new(bytes))?, client_move);
+
+    let poisoned = ClientMove { position: [1f32, f32::NAN, 3f32], hint: 0.5f64 };
+    let mut poisoned_bytes: Vec<u8> = Vec::new();
+    poisoned.simple_encode(&mut poisoned_bytes)?;
+    let mut decoder = Decoder::new(poisoned_bytes);
+    assert!(matches!(ClientMove::simple_decode(&mut decoder), Err(byte_transport::Error::NonFiniteFloat)));
+    assert!(!decoder.options.finite_floats, "field level finite must not leak into the decoder options");
+
+    // Only `position` is checked, so a NaN hint is accepted but always written as the canonical NaN
+    let nan_hint = ClientMove { position: [0f32; 3], hint: f64::from_bits(0xfff8_0000_0000_0001) };
+    let mut nan_hint_bytes: Vec<u8> = Vec::new();
+    nan_hint.simple_encode(&mut nan_hint_bytes)?;
+    assert_eq!(&nan_hint_bytes[12..], &byte_transport::CANONICAL_NAN_F64_BITS.to_le_bytes());
+    assert!(ClientMove::simple_decode(&mut Decoder::new(nan_hint_bytes))?.hint.is_nan());
+
+    let impulse = PhysicsInput::Impulse(vec![1f32, f32::INFINITY]);
+    let mut impulse_bytes: Vec<u8> = Vec::new();
+    impulse.simple_encode(&mut impulse_bytes)?;
+    assert!(matches!(PhysicsInput::simple_decode(&mut Decoder::new(impulse_bytes)), Err(byte_transport::Error::NonFiniteFloat)));
+
+    let spin = PhysicsInput::Spin { radians: Some(3.0f64) };
+    let mut spin_bytes: Vec<u8> = Vec::new();
+    spin.simple_encode(&mut spin_bytes)?;
+    assert_eq!(PhysicsInput::simple_decode(&mut Decoder::new(spin_bytes))?, spin);
+
+    Ok(())
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Eq, Debug)]
+#[bt(transparent)]
+struct Meters {
+    value: u32,
+    #[ig]
+    cached_feet: Option<u32>,
+}
+
+#[derive(ByteEncode, ByteDecode, Clone, Copy, PartialEq, Eq, Debug)]
+#[bt(into = "u32", from = "u32")]
+struct Handle {
+    index: u16,
+    generation: u16,
+}
+
+impl From<Handle> for u32 {
+    fn from(handle: Handle) -> Self {
+        (u32::from(handle.generation) << 16) | u32::from(handle.index)
+    }
+}
+
+impl From<u32> for Handle {
+    fn from(wire: u32) -> Self {
+        Handle { index: wire as u16, generation: (wire >> 16) as u16 }
+    }
+}
+
+#[derive(ByteEncode, ByteDecode, Clone, Copy, PartialEq, Eq, Debug)]
+#[bt(into = "u8", try_from = "u8")]
+enum Team {
+    Red,
+    Blue,
+}
+
+impl From<Team> for u8 {
+    fn from(team: Team) -> Self {
+        match team {
+            Team::Red => b'r',
+            Team::Blue => b'b',
+        }
+    }
+}
+
+impl TryFrom<u8> for Team {
+    type Error = String;
+
+    fn try_from(wire: u8) -> Result<Self, Self::Error> {
+        match wire {
+            b'r' => Ok(Team::Red),
+            b'b' => Ok(Team::Blue),
+            _ => Err(format!("unknown team byte {wire}")),
+        }
+    }
+}
+
+#[test]
+fn macro_proxy_test() -> Result<(), byte_transport::Error> {
+    let meters = Meters { value: 12u32, cached_feet: Some(39u32) };
+    let mut bytes: Vec<u8> = Vec::new();
+    meters.simple_encode(&mut bytes)?;
+    assert_eq!(bytes, 12u32.to_le_bytes());
+    assert_eq!(Meters::simple_decode(&mut Decoder::new(bytes))?, Meters { value: 12u32, cached_feet: None });
+
+    let handle = Handle { index: 3u16, generation: 1u16 };
+    let mut handle_bytes: Vec<u8> = Vec::new();
+    handle.simple_encode(&mut handle_bytes)?;
+    assert_eq!(handle_bytes, 0x0001_0003u32.to_le_bytes());
+    assert_eq!(Handle::simple_decode(&mut Decoder::new(handle_bytes))?, handle);
+
+    let mut team_bytes: Vec<u8> = Vec::new();
+    Team::Blue.simple_encode(&mut team_bytes)?;
+    assert_eq!(team_bytes, vec![b'b']);
+    assert_eq!(Team::simple_decode(&mut Decoder::new(team_bytes))?, Team::Blue);
+
+    match Team::simple_decode(&mut Decoder::new(vec![b'g'])) {
+        Err(byte_transport::Error::Conversion { type_name, reason }) => {
+            assert!(type_name.ends_with("Team"));
+            assert_eq!(reason, "unknown team byte 103");
+        },
+        result => panic!("Expected conversion error, got {:?}", result),
+    }
+
+    Ok(())
+}
+
+// The same message enum as seen by an old and a newer peer
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+#[bt(length_prefixed)]
+enum CommandV1 {
+    Move(i32, i32),
+    Stop,
+    #[bt(other)]
+    Unknown(u8, Vec<u8>),
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+#[bt(length_prefixed)]
+enum CommandV2 {
+    Move(i32, i32, i32),
+    Stop,
+    Jump { height: u16 },
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+struct BatchV1 {
+    command: CommandV1,
+    sequence: u32,
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+struct BatchV2 {
+    command: CommandV2,
+    sequence: u32,
+}
+
+#[test]
+fn macro_forward_compatible_enum_test() -> Result<(), byte_transport::Error> {
+    let mut bytes: Vec<u8> = Vec::new();
+    CommandV2::Jump { height: 7 }.simple_encode(&mut bytes)?;
+    assert_eq!(bytes, vec![2u8, 2u8, 7u8, 0u8]);
+
+    // An unknown variant is captured and forwarded verbatim
+    let unknown = CommandV1::simple_decode(&mut Decoder::new(bytes.clone()))?;
+    assert_eq!(unknown, CommandV1::Unknown(2, vec![7u8, 0u8]));
+    let mut forwarded: Vec<u8> = Vec::new();
+    unknown.simple_encode(&mut forwarded)?;
+    assert_eq!(forwarded, bytes);
+
+    // Trailing fields added by the newer peer are skipped without desyncing what follows
+    let mut batch_bytes: Vec<u8> = Vec::new();
+    BatchV2 { command: CommandV2::Move(1, 2, 3), sequence: 9 }.simple_encode(&mut batch_bytes)?;
+    let batch = BatchV1::simple_decode(&mut Decoder::new(batch_bytes))?;
+    assert_eq!(batch, BatchV1 { command: CommandV1::Move(1, 2), sequence: 9 });
+
+    let mut stop_bytes: Vec<u8> = Vec::new();
+    CommandV1::Stop.simple_encode(&mut stop_bytes)?;
+    assert_eq!(stop_bytes, vec![1u8, 0u8]);
+    assert_eq!(CommandV2::simple_decode(&mut Decoder::new(stop_bytes))?, CommandV2::Stop);
+
+    Ok(())
+}
+
+macro_rules! optional_state {
+    ($name:ident $(, #[$container:meta])?) => {
+        #[derive(ByteEncode, ByteDecode, PartialEq, Debug, Default)]
+        $(#[$container])?
+        struct $name {
+            id: u32,
+            health: Option<u16>,
+            name: Option<String>,
+            target: Option<SubStruct>,
+            speed: Option<f32>,
+            #[ig]
+            cached: Option<u64>,
+            ammo: Option<u8>,
+            armor: Option<Option<u8>>,
+            team: Option<i8>,
+            shield: Option<u32>,
+            level: Option<u16>,
+            rank: Option<u64>,
+        }
+
+        impl $name {
+            // Bit `n` of `combination` decides whether the nth optional field is present
+            fn from_combination(combination: u16) -> Self {
+                let present = |bit: u16| combination & (1 << bit) != 0;
+                $name {
+                    id: 7,
+                    health: present(0).then_some(90),
+                    name: present(1).then(|| String::from("scout")),
+                    target: present(2).then_some(SubStruct { b: true, integer_32: 0 }),
+                    speed: present(3).then_some(2.5),
+                    cached: None,
+                    ammo: present(4).then_some(30),
+                    armor: present(5).then_some(None),
+                    team: present(6).then_some(-1),
+                    shield: present(7).then_some(500),
+                    level: present(8).then_some(12),
+                    rank: present(9).then_some(3),
+                }
+            }
+        }
+    };
+}
+
+optional_state!(FlaggedState);
+optional_state!(MaskedState, #[bt(presence_bitmask)]);
+
+#[test]
+fn macro_presence_bitmask_test() -> Result<(), byte_transport::Error> {
+    let mut empty: Vec<u8> = Vec::new();
+    MaskedState::default().simple_encode(&mut empty)?;
+    assert_eq!(empty, vec![0u8, 0u8, 0u8, 0u8, 0u8, 0u8]);
+
+    // Every combination of the ten optional fields, against the flag byte per field encoding
+    for combination in 0u16..(1 << 10) {
+        let flagged = FlaggedState::from_combination(combination);
+        let masked = MaskedState::from_combination(combination);
+
+        let mut flagged_bytes: Vec<u8> = Vec::new();
+        flagged.simple_encode(&mut flagged_bytes)?;
+        let mut masked_bytes: Vec<u8> = Vec::new();
+        MaskedState { cached: Some(99), ..MaskedState::from_combination(combination) }.simple_encode(&mut masked_bytes)?;
+        // Ten flag bytes become a two byte mask
+        assert_eq!(masked_bytes.len(), flagged_bytes.len() - 10 + 2);
+
+        assert_eq!(FlaggedState::simple_decode(&mut Decoder::new(flagged_bytes))?, flagged);
+        assert_eq!(MaskedState::simple_decode(&mut Decoder::new(masked_bytes))?, masked);
+    }
+
+    Ok(())
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+struct SaveHeader {
+    #[bt(magic = b"BTSV")]
+    magic: [u8; 4],
+    version: u16,
+    #[bt(pad = 2)]
+    reserved: (),
+    slot: u8,
+    #[bt(checksum = crc32)]
+    crc: u32,
+    #[bt(checksum = xor8)]
+    check: u8,
+}
+
+#[test]
+fn macro_layout_fields_test() -> Result<(), byte_transport::Error> {
+    let header = SaveHeader { magic: [0u8; 4], version: 3, reserved: (), slot: 1, crc: 0, check: 0 };
+    let mut bytes: Vec<u8> = Vec::new();
+    header.simple_encode(&mut bytes)?;
+    assert_eq!(&bytes[..9], b"BTSV\x03\x00\x00\x00\x01");
+    assert_eq!(bytes[9..13], byte_transport::Crc32::compute(&bytes[..9]).to_le_bytes());
+    assert_eq!(bytes[13], byte_transport::Xor8::compute(&bytes[..13]));
+
+    let decoded = SaveHeader::simple_decode(&mut Decoder::new(bytes.clone()))?;
+    assert_eq!(decoded.magic, *b"BTSV");
+    assert_eq!((decoded.version, decoded.slot), (3, 1));
+
+    let mut wrong_magic = bytes.clone();
+    wrong_magic[0] = b'X';
+    match SaveHeader::simple_decode(&mut Decoder::new(wrong_magic)) {
+        Err(byte_transport::Error::MagicMismatch { field, expected, found }) => {
+            assert_eq!(field, "magic");
+            assert_eq!(expected, b"BTSV");
+            assert_eq!(found, b"XTSV");
+        },
+        result => panic!("Expected magic mismatch, got {:?}", result),
+    }
+
+    let mut corrupted = bytes.clone();
+    corrupted[8] = 2;
+    match SaveHeader::simple_decode(&mut Decoder::new(corrupted)) {
+        Err(byte_transport::Error::ChecksumMismatch { field, .. }) => assert_eq!(field, "crc"),
+        result => panic!("Expected checksum mismatch, got {:?}", result),
+    }
+
+    Ok(())
+}
+
+// Fields named like the locals the derives generate must not clash with them
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+#[bt(length_prefixed)]
+enum PayloadNamed {
+    Data { payload: u16, payload_len: u8, payload_decoder: u8 },
+    Empty,
+}
+
+#[test]
+fn macro_payload_named_fields_test() -> Result<(), byte_transport::Error> {
+    let payload = PayloadNamed::Data { payload: 5, payload_len: 6, payload_decoder: 7 };
+    let mut bytes: Vec<u8> = Vec::new();
+    payload.simple_encode(&mut bytes)?;
+    assert_eq!(bytes, vec![0u8, 4u8, 5u8, 0u8, 6u8, 7u8]);
+    assert_eq!(PayloadNamed::simple_decode(&mut Decoder::new(bytes))?, payload);
+    Ok(())
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+#[bt(presence_bitmask)]
+struct PresenceNamed {
+    presence: Option<u8>,
+    flags: Option<u16>,
+}
+
+#[test]
+fn macro_presence_named_field_test() -> Result<(), byte_transport::Error> {
+    let presence = PresenceNamed { presence: Some(9), flags: None };
+    let mut bytes: Vec<u8> = Vec::new();
+    presence.simple_encode(&mut bytes)?;
+    assert_eq!(bytes, vec![1u8, 9u8]);
+    assert_eq!(PresenceNamed::simple_decode(&mut Decoder::new(bytes))?, presence);
+    Ok(())
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+struct ChecksumNamed {
+    checksum_start: u32,
+    #[bt(checksum = sum8)]
+    checksum: u8,
+}
+
+#[test]
+fn macro_checksum_named_fields_test() -> Result<(), byte_transport::Error> {
+    let checksum = ChecksumNamed { checksum_start: 0x0102_0304, checksum: 0 };
+    let mut bytes: Vec<u8> = Vec::new();
+    checksum.simple_encode(&mut bytes)?;
+    assert_eq!(bytes, vec![4u8, 3u8, 2u8, 1u8, 10u8]);
+    assert_eq!(ChecksumNamed::simple_decode(&mut Decoder::new(bytes))?, ChecksumNamed { checksum_start: 0x0102_0304, checksum: 10 });
+    Ok(())
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+struct ParameterNamed {
+    decoder: u8,
+    bytes: u16,
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+enum ParameterNamedEnum {
+    Data { bytes: u8, decoder: u16 },
+    Empty,
+}
+
+#[test]
+fn macro_parameter_named_fields_test() -> Result<(), byte_transport::Error> {
+    let parameters = ParameterNamed { decoder: 1, bytes: 2 };
+    let mut bytes: Vec<u8> = Vec::new();
+    parameters.simple_encode(&mut bytes)?;
+    assert_eq!(bytes, vec![1u8, 2u8, 0u8]);
+    assert_eq!(ParameterNamed::simple_decode(&mut Decoder::new(bytes))?, parameters);
+
+    let parameters = ParameterNamedEnum::Data { bytes: 3, decoder: 4 };
+    let mut bytes: Vec<u8> = Vec::new();
+    parameters.simple_encode(&mut bytes)?;
+    assert_eq!(bytes, vec![0u8, 3u8, 4u8, 0u8]);
+    assert_eq!(ParameterNamedEnum::simple_decode(&mut Decoder::new(bytes))?, parameters);
+    Ok(())
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+struct ReplayHeader {
+    frame_count: u16,
+    kind: u8,
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+enum ReplayEvent {
+    #[bt(tag = 1)]
+    Input { buttons: u8 },
+    #[bt(tag = 4)]
+    Chat(String),
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+struct Replay {
+    header: ReplayHeader,
+    #[bt(count = "header.frame_count")]
+    frames: Vec<u32>,
+    #[bt(tag_from = "header.kind")]
+    event: ReplayEvent,
+}
+
+#[test]
+fn macro_external_fields_test() -> Result<(), byte_transport::Error> {
+    let replay = Replay {
+        header: ReplayHeader { frame_count: 2, kind: 4 },
+        frames: vec![10, 11],
+        event: ReplayEvent::Chat(String::from("gg")),
+    };
+    let mut bytes: Vec<u8> = Vec::new();
+    replay.simple_encode(&mut bytes)?;
+
+    // No length prefix on the frames and no tag on the event, both come from the header
+    let mut expected: Vec<u8> = vec![2u8, 0u8, 4u8];
+    expected.extend_from_slice(&10u32.to_le_bytes());
+    expected.extend_from_slice(&11u32.to_le_bytes());
+    String::from("gg").simple_encode(&mut expected)?;
+    assert_eq!(bytes, expected);
+    assert_eq!(Replay::simple_decode(&mut Decoder::new(bytes))?, replay);
+
+    let mismatched = Replay {
+        header: ReplayHeader { frame_count: 3, kind: 1 },
+        frames: vec![10, 11],
+        event: ReplayEvent::Input { buttons: 0 },
+    };
+    match mismatched.simple_encode(&mut Vec::new()) {
+        Err(byte_transport::Error::Validation { field, .. }) => assert_eq!(field, "frames"),
+        result => panic!("Expected validation error, got {:?}", result),
+    }
+
+    match Replay::simple_decode(&mut Decoder::new(vec![0u8, 0u8, 9u8])) {
+        Err(byte_transport::Error::DecodingEnumVariant(9)) => {},
+        result => panic!("Expected unknown variant error, got {:?}", result),
+    }
+
+    Ok(())
+}
+
+#[repr(C)]
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+#[bt(c_layout, size = 16, align = 8)]
+struct SimHeader {
+    kind: u8,
+    timestamp: u64,
+}
+
+#[repr(C)]
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+#[bt(c_layout, endian = "big")]
+struct NetAddress {
+    port: u16,
+    flags: u8,
+    addr: u32,
+}
+
+#[repr(C)]
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug, Clone, Copy)]
+#[bt(pod)]
+struct Vec3 {
+    x: f32,
+    y: f32,
+    z: f32,
+}
+
+#[repr(C)]
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+#[bt(c_layout)]
+struct SimBody {
+    id: u8,
+    position: Vec3,
+    flags: [u16; 2],
+    active: bool,
+}
+
+#[test]
+fn macro_c_layout_test() -> Result<(), byte_transport::Error> {
+    let header = SimHeader { kind: 3, timestamp: 0x0102_0304_0506_0708 };
+    let mut bytes: Vec<u8> = Vec::new();
+    header.simple_encode(&mut bytes)?;
+    let mut expected = vec![3u8, 0, 0, 0, 0, 0, 0, 0];
+    expected.extend_from_slice(&0x0102_0304_0506_0708u64.to_le_bytes());
+    assert_eq!(bytes, expected);
+    assert_eq!(SimHeader::simple_decode(&mut Decoder::new(bytes))?, header);
+
+    let address = NetAddress { port: 8080, flags: 1, addr: 0x7f00_0001 };
+    let mut address_bytes: Vec<u8> = Vec::new();
+    address.simple_encode(&mut address_bytes)?;
+    assert_eq!(address_bytes, vec![0x1f, 0x90, 1, 0, 0x7f, 0, 0, 1]);
+    assert_eq!(NetAddress::simple_decode(&mut Decoder::new(address_bytes))?, address);
+
+    // The pod fast path writes the value's memory as it is
+    let position = Vec3 { x: 1.0, y: -2.0, z: 0.5 };
+    let mut position_bytes: Vec<u8> = Vec::new();
+    position.simple_encode(&mut position_bytes)?;
+    assert_eq!(position_bytes, byte_transport::Pod::as_bytes(&position));
+
+    let body = SimBody { id: 9, position, flags: [1, 2], active: true };
+    let mut body_bytes: Vec<u8> = Vec::new();
+    body.simple_encode(&mut body_bytes)?;
+    assert_eq!(body_bytes.len(), std::mem::size_of::<SimBody>());
+    assert_eq!(body_bytes[..4], [9u8, 0, 0, 0]);
+    assert_eq!(body_bytes[4..16], position_bytes);
+    assert_eq!(SimBody::simple_decode(&mut Decoder::new(body_bytes))?, body);
+
+    match SimHeader::simple_decode(&mut Decoder::new(vec![3u8; 10])) {
+        Err(byte_transport::Error::UnexpectedEof { needed: 16, remaining: 10 }) => {},
+        result => panic!("Expected unexpected eof, got {:?}", result),
+    }
+
+    Ok(())
+}
+
+mod game_protocol {
+    use super::{SubStruct, TestEnum};
+
+    byte_transport::protocol! {
+        client_to_server {
+            Move(TestEnum) = 1,
+            Chat(String) = 2,
+        }
+        server_to_client {
+            Welcome(u32) = 1,
+            Spawn(SubStruct) = 7,
+        }
+    }
+}
+
+// The same protocol with one tag changed
+mod game_protocol_v2 {
+    use super::{SubStruct, TestEnum};
+
+    byte_transport::protocol! {
+        client_to_server {
+            Move(TestEnum) = 1,
+            Chat(String) = 3,
+        }
+        server_to_client {
+            Welcome(u32) = 1,
+            Spawn(SubStruct) = 7,
+        }
+    }
+}
+
+#[derive(Default)]
+struct ChatServer {
+    moves: usize,
+    chat: Vec<String>,
+}
+
+impl game_protocol::ClientToServerHandler for ChatServer {
+    fn on_move(&mut self, _message: TestEnum) {
+        self.moves += 1;
+    }
+
+    fn on_chat(&mut self, message: String) {
+        self.chat.push(message);
+    }
+}
+
+#[test]
+fn macro_protocol_test() -> Result<(), byte_transport::Error> {
+    use game_protocol::{ClientToServer, ServerToClient};
+
+    let mut bytes: Vec<u8> = Vec::new();
+    ClientToServer::Chat(String::from("hi")).simple_encode(&mut bytes)?;
+    ClientToServer::Move(TestEnum::B(1, 2)).simple_encode(&mut bytes)?;
+    assert_eq!(bytes[..2], 2u16.to_le_bytes());
+
+    let mut server = ChatServer::default();
+    let mut decoder = Decoder::new(bytes);
+    while !decoder.is_at_end() {
+        ClientToServer::simple_decode(&mut decoder)?.dispatch(&mut server);
+    }
+    assert_eq!(server.chat, vec![String::from("hi")]);
+    assert_eq!(server.moves, 1);
+
+    let mut spawn: Vec<u8> = Vec::new();
+    ServerToClient::Spawn(SubStruct { b: true, integer_32: 0 }).simple_encode(&mut spawn)?;
+    assert_eq!(spawn, vec![7u8, 0u8, 1u8]);
+    match ClientToServer::simple_decode(&mut Decoder::new(spawn)) {
+        Err(byte_transport::Error::DecodingEnumVariant(7)) => {},
+        result => panic!("Expected unknown variant error, got {:?}", result),
+    }
+
+    assert_ne!(game_protocol::PROTOCOL_FINGERPRINT, game_protocol_v2::PROTOCOL_FINGERPRINT);
+    assert_ne!(game_protocol::ClientToServer::FINGERPRINT, game_protocol_v2::ClientToServer::FINGERPRINT);
+    assert_eq!(game_protocol::ServerToClient::FINGERPRINT, game_protocol_v2::ServerToClient::FINGERPRINT);
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use byte_transport::{ByteDecode, ByteEncode, FrameReader, FrameWriter, Message, MessageRegistry};
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+struct Chat {
+    from: String,
+    text: String,
+}
+
+impl Message for Chat {
+    const ID: u16 = 1;
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+struct Position {
+    x: f32,
+    y: f32,
+}
+
+impl Message for Position {
+    const ID: u16 = 2;
+}
+
+#[derive(Default)]
+struct ServerState {
+    chat_log: Vec<String>,
+    last_position: Option<(f32, f32)>,
+    pings: u32,
+}
+
+// Each module adds its own messages to the shared registry
+mod chat {
+    use super::*;
+
+    pub fn register(registry: &mut MessageRegistry<ServerState>) -> Result<(), byte_transport::Error> {
+        registry.register(|state: &mut ServerState, chat: Chat| state.chat_log.push(format!("{}: {}", chat.from, chat.text)))
+    }
+}
+
+mod movement {
+    use super::*;
+
+    pub fn register(registry: &mut MessageRegistry<ServerState>) -> Result<(), byte_transport::Error> {
+        registry.register(|state: &mut ServerState, position: Position| state.last_position = Some((position.x, position.y)))?;
+        // A plain u8 has no Message impl, so it gets an explicit id
+        registry.register_with_id(100, |state: &mut ServerState, _ping: u8| state.pings += 1)
+    }
+}
+
+#[test]
+fn registry_dispatch() -> Result<(), byte_transport::Error> {
+    let mut registry = MessageRegistry::new();
+    chat::register(&mut registry)?;
+    movement::register(&mut registry)?;
+    assert_eq!(registry.id_of::<Position>(), Some(2));
+    assert_eq!(registry.type_name_of(1), Some(std::any::type_name::<Chat>()));
+
+    // Messages travel inside frames, one message per frame
+    let frames = FrameWriter::new();
+    let mut stream: Vec<u8> = Vec::new();
+    for message in [
+        encode(&registry, &Chat { from: String::from("ada"), text: String::from("hi") })?,
+        encode(&registry, &Position { x: 1.5, y: -2.0 })?,
+        encode(&registry, &0u8)?,
+        vec![0x39, 0x05, 0xff],
+        encode(&registry, &0u8)?,
+    ] {
+        frames.write_payload(&message, &mut stream)?;
+    }
+
+    let mut state = ServerState::default();
+    let mut reader = FrameReader::new();
+    reader.push(&stream);
+    let mut unknown = Vec::new();
+    while let Some(frame) = reader.next_frame()? {
+        match registry.dispatch(&mut state, &frame) {
+            Err(byte_transport::Error::UnknownMessageId(id)) => unknown.push(id),
+            result => result?,
+        }
+    }
+
+    assert_eq!(state.chat_log, vec![String::from("ada: hi")]);
+    assert_eq!(state.last_position, Some((1.5, -2.0)));
+    assert_eq!(state.pings, 2);
+    assert_eq!(unknown, vec![0x0539]);
+
+    assert!(matches!(registry.register_with_id(1, |_: &mut ServerState, _: u32| {}), Err(byte_transport::Error::DuplicateMessageId(1))));
+    assert!(matches!(registry.register_with_id(7, |_: &mut ServerState, _: Chat| {}), Err(byte_transport::Error::DuplicateMessageId(1))));
+    assert!(matches!(
+        encode(&registry, &5u64),
+        Err(byte_transport::Error::UnregisteredMessage { type_name: "u64" })
+    ));
+    Ok(())
+}
+
+fn encode<T: ByteEncode + 'static>(registry: &MessageRegistry<ServerState>, message: &T) -> Result<Vec<u8>, byte_transport::Error> {
+    let mut bytes = Vec::new();
+    registry.encode(message, &mut bytes)?;
+    Ok(bytes)
+}
@@ -0,0 +1,152 @@
+mod common;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use byte_transport::{ByteDecode, ByteEncode, DecoderOptions, Error, MemoryTransport, ReliableChannel, ReliableConfig, Transport};
+use common::{udp_pair, Lossy};
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+struct PlayerAction {
+    sequence: u32,
+    kind: u8,
+    target: [f32; 3],
+}
+
+fn action(sequence: u32) -> PlayerAction {
+    PlayerAction { sequence, kind: (sequence % 5) as u8, target: [sequence as f32, 1.0, -2.5] }
+}
+
+// Sends `count` actions each way while pumping both ends, checking both arrive complete and in order
+fn exchange<A: Transport, B: Transport>(left: &mut ReliableChannel<A>, right: &mut ReliableChannel<B>, count: u32) {
+    let (mut left_received, mut right_received) = (0, 0);
+    let (mut left_sent, mut right_sent) = (0, 0);
+    let started = Instant::now();
+    while left_received < count || right_received < count || left.in_flight() > 0 || right.in_flight() > 0 {
+        assert!(started.elapsed() < Duration::from_secs(20), "stalled at {left_received}/{right_received} of {count}");
+        // Queue a burst per tick, like a game sending its inputs
+        for _ in 0..16 {
+            if left_sent < count {
+                left.send(&action(left_sent)).unwrap();
+                left_sent += 1;
+            }
+            if right_sent < count {
+                right.send(&action(right_sent + 10_000)).unwrap();
+                right_sent += 1;
+            }
+        }
+        left.update().unwrap();
+        right.update().unwrap();
+        while let Some(received) = right.recv::<PlayerAction>().unwrap() {
+            assert_eq!(received, action(right_received));
+            right_received += 1;
+        }
+        while let Some(received) = left.recv::<PlayerAction>().unwrap() {
+            assert_eq!(received, action(left_received + 10_000));
+            left_received += 1;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!((left_received, right_received), (count, count));
+}
+
+#[test]
+fn clean_udp_loopback() {
+    let (left, right) = udp_pair();
+    let (mut left, mut right) = (ReliableChannel::new(left), ReliableChannel::new(right));
+    exchange(&mut left, &mut right, 500);
+    assert!(left.rtt() < Duration::from_millis(100));
+}
+
+#[test]
+fn lossy_udp_loopback() {
+    let (left, right) = udp_pair();
+    let config = ReliableConfig { min_resend_delay: Duration::from_millis(5), ..ReliableConfig::default() };
+    let mut left = ReliableChannel::with_config(Lossy::new(left, 0x9e37_79b9_7f4a_7c15, 30, 10), config);
+    let mut right = ReliableChannel::with_config(Lossy::new(right, 0x2545_f491_4f6c_dd1d, 30, 10), config);
+    exchange(&mut left, &mut right, 1000);
+}
+
+#[test]
+fn duplicates_are_suppressed() {
+    let (left, right) = MemoryTransport::pair();
+    let mut left = ReliableChannel::new(Lossy::new(left, 7, 0, 100));
+    let mut right = ReliableChannel::new(right);
+    for sequence in 0..10 {
+        left.send(&action(sequence)).unwrap();
+    }
+    // Every packet arrives twice
+    left.update().unwrap();
+    right.update().unwrap();
+    let mut received = Vec::new();
+    while let Some(action) = right.recv::<PlayerAction>().unwrap() {
+        received.push(action.sequence);
+    }
+    assert_eq!(received, (0..10).collect::<Vec<_>>());
+    right.update().unwrap();
+    assert_eq!(right.recv::<PlayerAction>().unwrap(), None);
+}
+
+#[test]
+fn sequence_numbers_wrap() {
+    let (left, right) = MemoryTransport::pair();
+    // One message per packet, so packet sequences wrap along with message ids
+    let config = ReliableConfig { max_packet_size: 24, ..ReliableConfig::default() };
+    let mut left = ReliableChannel::with_config(left, config);
+    let mut right = ReliableChannel::with_config(right, config);
+    let mut next = 0u32;
+    for batch in 0..3000u32 {
+        for _ in 0..30 {
+            left.send(&batch).unwrap();
+        }
+        left.update().unwrap();
+        right.update().unwrap();
+        while let Some(value) = right.recv::<u32>().unwrap() {
+            assert_eq!(value, next / 30);
+            next += 1;
+        }
+        left.update().unwrap();
+        assert_eq!(left.in_flight(), 0);
+    }
+    assert_eq!(next, 90_000);
+}
+
+#[test]
+fn send_window() {
+    let (left, _right) = MemoryTransport::pair();
+    let mut left = ReliableChannel::with_config(left, ReliableConfig { max_in_flight: 4, ..ReliableConfig::default() });
+    for sequence in 0..4 {
+        left.send(&action(sequence)).unwrap();
+    }
+    assert!(matches!(left.send(&action(4)), Err(Error::SendWindowFull { capacity: 4 })));
+    assert!(matches!(left.send_bytes(vec![0; 100_000]), Err(Error::FrameTooLarge { size: 100_000, .. })));
+}
+
+#[test]
+fn decoder_options() {
+    let (left, right) = MemoryTransport::pair();
+    let mut left = ReliableChannel::new(left);
+    let mut right = ReliableChannel::new(right).with_decoder_options(DecoderOptions { finite_floats: true });
+    left.send(&PlayerAction { sequence: 0, kind: 0, target: [f32::INFINITY, 0.0, 0.0] }).unwrap();
+    left.update().unwrap();
+    right.update().unwrap();
+    assert!(matches!(right.recv::<PlayerAction>(), Err(Error::NonFiniteFloat)));
+}
+
+#[test]
+fn truncated_packets_are_rejected() {
+    let (mut raw, channel) = MemoryTransport::pair();
+    let mut channel = ReliableChannel::new(channel);
+    raw.send_bytes(&[0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 9, 0, 1]).unwrap();
+    channel.update().unwrap();
+    assert_eq!(channel.dropped_packets(), 1);
+    assert!(matches!(channel.last_packet_error(), Some(Error::UnexpectedEof { needed: 9, remaining: 1 })));
+    assert_eq!(channel.recv_bytes(), None);
+
+    // Packets behind a bad one are still read
+    raw.send_bytes(&[0]).unwrap();
+    raw.send_bytes(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 1, 0, 5]).unwrap();
+    channel.update().unwrap();
+    assert_eq!(channel.dropped_packets(), 2);
+    assert_eq!(channel.recv_bytes(), Some(vec![5]));
+}
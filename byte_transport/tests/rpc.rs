@@ -0,0 +1,231 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use byte_transport::{ByteDecode, ByteEncode, DecoderOptions, Error, Request, RpcClient, RpcServer};
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+struct InventoryQuery {
+    player: u32,
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+struct Inventory {
+    items: Vec<u16>,
+}
+
+impl Request for InventoryQuery {
+    type Response = Inventory;
+    const METHOD: u16 = 1;
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+struct Login {
+    name: String,
+}
+
+impl Request for Login {
+    type Response = u64;
+    const METHOD: u16 = 2;
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+struct Logout;
+
+impl Request for Logout {
+    type Response = bool;
+    const METHOD: u16 = 3;
+}
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug)]
+struct Aim {
+    yaw: f32,
+}
+
+impl Request for Aim {
+    type Response = f32;
+    const METHOD: u16 = 4;
+}
+
+fn server() -> RpcServer<u64> {
+    let mut server = RpcServer::new();
+    server.register(|_: &mut u64, query: InventoryQuery| Ok(Inventory { items: vec![query.player as u16, 7] })).unwrap();
+    server
+        .register(|sessions: &mut u64, login: Login| {
+            if login.name.is_empty() {
+                return Err(Error::Validation { field: "name".to_string(), reason: "empty".to_string() });
+            }
+            *sessions += 1;
+            Ok(*sessions)
+        })
+        .unwrap();
+    server
+}
+
+// Client whose requests land in the returned channel instead of a socket
+fn client() -> (RpcClient, mpsc::Receiver<Vec<u8>>) {
+    let (sender, receiver) = mpsc::channel();
+    let client = RpcClient::new(move |frame| sender.send(frame).map_err(|_| Error::Cancelled));
+    (client, receiver)
+}
+
+#[test]
+fn blocking_call_round_trip() {
+    let (client, requests) = client();
+    let mut server = server();
+    let mut sessions = 0;
+
+    let pending = client.call(&InventoryQuery { player: 3 }).unwrap();
+    let response = server.handle_request(&mut sessions, &requests.recv().unwrap()).unwrap();
+    assert!(client.handle_response(&response).unwrap());
+    assert_eq!(pending.wait().unwrap(), Inventory { items: vec![3, 7] });
+    assert_eq!(client.pending_calls(), 0);
+}
+
+#[test]
+fn responses_out_of_order() {
+    let (client, requests) = client();
+    let mut server = server();
+    let mut sessions = 0;
+
+    let first = client.call(&Login { name: "ana".to_string() }).unwrap();
+    let second = client.call(&InventoryQuery { player: 9 }).unwrap();
+    assert_ne!(first.call_id(), second.call_id());
+    let first_frame = requests.recv().unwrap();
+    let second_frame = requests.recv().unwrap();
+
+    client.handle_response(&server.handle_request(&mut sessions, &second_frame).unwrap()).unwrap();
+    client.handle_response(&server.handle_request(&mut sessions, &first_frame).unwrap()).unwrap();
+    assert_eq!(second.wait().unwrap(), Inventory { items: vec![9, 7] });
+    assert_eq!(first.wait().unwrap(), 1);
+}
+
+#[test]
+fn async_call_with_server_thread() {
+    let (client, requests) = client();
+    let client = std::sync::Arc::new(client);
+    let server_client = client.clone();
+    let server_thread = thread::spawn(move || {
+        let mut server = server();
+        let mut sessions = 0;
+        // The thread holds the client and with it the sending half, so stop after both logins
+        for frame in requests.iter().take(2) {
+            let response = server.handle_request(&mut sessions, &frame).unwrap();
+            server_client.handle_response(&response).unwrap();
+        }
+    });
+
+    let sessions = futures::executor::block_on(async {
+        let mut sessions = Vec::new();
+        for name in ["ana", "ben"] {
+            sessions.push(client.call(&Login { name: name.to_string() }).unwrap().await.unwrap());
+        }
+        sessions
+    });
+    assert_eq!(sessions, vec![1, 2]);
+    server_thread.join().unwrap();
+}
+
+#[test]
+fn remote_errors() {
+    let (client, requests) = client();
+    let mut server = server();
+    let mut sessions = 0;
+
+    let pending = client.call(&Login { name: String::new() }).unwrap();
+    client.handle_response(&server.handle_request(&mut sessions, &requests.recv().unwrap()).unwrap()).unwrap();
+    match pending.wait() {
+        Err(Error::Remote(message)) => assert!(message.contains("empty"), "{message}"),
+        other => panic!("expected a remote error, got {other:?}"),
+    }
+
+    // A method nobody registered still gets an answer
+    let pending = client.call(&Logout).unwrap();
+    client.handle_response(&server.handle_request(&mut sessions, &requests.recv().unwrap()).unwrap()).unwrap();
+    assert!(matches!(pending.wait(), Err(Error::Remote(message)) if message.contains("UnknownMessageId(3)")));
+}
+
+#[test]
+fn timeouts() {
+    let (client, _requests) = client();
+
+    let pending = client.call(&InventoryQuery { player: 1 }).unwrap();
+    assert!(matches!(pending.wait_timeout(Duration::from_millis(10)), Err(Error::Timeout)));
+
+    let pending = client.call_with_timeout(&InventoryQuery { player: 1 }, Duration::from_millis(10)).unwrap();
+    assert!(matches!(pending.wait(), Err(Error::Timeout)));
+
+    // Async calls time out once the deadline has been swept
+    let pending = client.call_with_timeout(&InventoryQuery { player: 1 }, Duration::ZERO).unwrap();
+    client.expire_timeouts();
+    assert!(matches!(futures::executor::block_on(pending), Err(Error::Timeout)));
+    assert_eq!(client.pending_calls(), 0);
+
+    // Polling past the deadline fails even if nothing swept it
+    let pending = client.call_with_timeout(&InventoryQuery { player: 1 }, Duration::ZERO).unwrap();
+    assert!(matches!(futures::executor::block_on(pending), Err(Error::Timeout)));
+}
+
+#[test]
+fn late_responses_after_timeout() {
+    let (client, requests) = client();
+    let mut server = server();
+    let mut sessions = 0;
+
+    let pending = client.call_with_timeout(&InventoryQuery { player: 4 }, Duration::ZERO).unwrap();
+    client.expire_timeouts();
+    let response = server.handle_request(&mut sessions, &requests.recv().unwrap()).unwrap();
+    assert!(!client.handle_response(&response).unwrap());
+    assert!(matches!(futures::executor::block_on(pending), Err(Error::Timeout)));
+}
+
+#[test]
+fn decoder_options() {
+    let finite = DecoderOptions { finite_floats: true };
+    let (sender, requests) = mpsc::channel();
+    let client = RpcClient::new(move |frame| sender.send(frame).map_err(|_| Error::Cancelled)).with_decoder_options(finite);
+    let mut server = RpcServer::<u64>::new().with_decoder_options(finite);
+    server.register(|_: &mut u64, aim: Aim| Ok(aim.yaw * f32::INFINITY)).unwrap();
+
+    // The server refuses a non-finite request, and the client a non-finite response
+    let pending = client.call(&Aim { yaw: f32::NAN }).unwrap();
+    client.handle_response(&server.handle_request(&mut 0, &requests.recv().unwrap()).unwrap()).unwrap();
+    assert!(matches!(pending.wait(), Err(Error::Remote(message)) if message.contains("NonFiniteFloat")));
+    let pending = client.call(&Aim { yaw: 1.0 }).unwrap();
+    client.handle_response(&server.handle_request(&mut 0, &requests.recv().unwrap()).unwrap()).unwrap();
+    assert!(matches!(pending.wait(), Err(Error::NonFiniteFloat)));
+}
+
+#[test]
+fn cancelled_calls_drop_late_responses() {
+    let (client, requests) = client();
+    let mut server = server();
+    let mut sessions = 0;
+
+    let pending = client.call(&InventoryQuery { player: 2 }).unwrap();
+    pending.cancel();
+    assert_eq!(client.pending_calls(), 0);
+    let response = server.handle_request(&mut sessions, &requests.recv().unwrap()).unwrap();
+    assert!(!client.handle_response(&response).unwrap());
+}
+
+#[test]
+fn failed_send_releases_call() {
+    let client = RpcClient::new(|_| Err(Error::Cancelled));
+    assert!(matches!(client.call(&Logout), Err(Error::Cancelled)));
+    assert_eq!(client.pending_calls(), 0);
+}
+
+#[test]
+fn frame_kinds() {
+    let (client, requests) = client();
+    let mut server = server();
+    let _pending = client.call(&Logout).unwrap();
+    let request = requests.recv().unwrap();
+    assert!(byte_transport::is_request(&request));
+    let response = server.handle_request(&mut 0, &request).unwrap();
+    assert!(!byte_transport::is_request(&response));
+    assert!(server.handle_request(&mut 0, &response).is_err());
+    assert!(client.handle_response(&request).is_err());
+    assert!(matches!(server.register(|_: &mut u64, _: Logout| Ok(true)).and_then(|_| server.register(|_: &mut u64, _: Logout| Ok(true))), Err(Error::DuplicateMethod(3))));
+}
@@ -0,0 +1,173 @@
+mod common;
+
+use std::io::Write;
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use byte_transport::{
+    ByteDecode, ByteEncode, DecoderOptions, Error, MemoryTransport, ReliableChannel, TcpTransport, Transport, UdpTransport, MAX_DATAGRAM_SIZE,
+};
+use common::udp_pair;
+
+#[derive(ByteEncode, ByteDecode, PartialEq, Debug, Clone)]
+struct Snapshot {
+    tick: u32,
+    positions: Vec<[f32; 2]>,
+    note: String,
+}
+
+fn snapshot(tick: u32) -> Snapshot {
+    Snapshot { tick, positions: vec![[tick as f32, 0.5]; tick as usize % 4], note: format!("tick {tick}") }
+}
+
+// Same conversation over every transport, each side echoing with the tick bumped
+fn exchange(left: &mut impl Transport, right: &mut impl Transport) {
+    for tick in 0..8 {
+        left.send(&snapshot(tick)).unwrap();
+        let received: Snapshot = right.recv().unwrap();
+        assert_eq!(received, snapshot(tick));
+        right.send(&snapshot(received.tick + 100)).unwrap();
+        assert_eq!(left.recv::<Snapshot>().unwrap(), snapshot(tick + 100));
+    }
+
+    // Empty payloads still arrive as one message each
+    left.send_bytes(&[]).unwrap();
+    left.send(&7u16).unwrap();
+    assert_eq!(right.recv_bytes().unwrap(), Vec::<u8>::new());
+    assert_eq!(right.recv::<u16>().unwrap(), 7);
+}
+
+#[test]
+fn memory_round_trip() {
+    let (mut left, mut right) = MemoryTransport::pair();
+    exchange(&mut left, &mut right);
+
+    assert_eq!(right.try_recv::<Snapshot>().unwrap(), None);
+    left.send(&snapshot(3)).unwrap();
+    left.send(&snapshot(4)).unwrap();
+    assert_eq!(right.try_recv::<Snapshot>().unwrap(), Some(snapshot(3)));
+    assert!(right.try_recv_bytes().unwrap().is_some());
+
+    drop(left);
+    assert!(matches!(right.recv_bytes(), Err(Error::Io(_))));
+    assert!(matches!(right.try_recv_bytes(), Err(Error::Io(_))));
+    assert!(matches!(right.send(&1u8), Err(Error::Io(_))));
+}
+
+#[test]
+fn decoder_options() {
+    let (mut left, mut right) = MemoryTransport::pair();
+    let options = DecoderOptions { finite_floats: true };
+    left.send(&f32::NAN).unwrap();
+    left.send(&[1.0f32, f32::INFINITY]).unwrap();
+    left.send(&2.5f32).unwrap();
+    assert!(matches!(right.recv_with_options::<f32>(options), Err(Error::NonFiniteFloat)));
+    assert!(matches!(right.try_recv_with_options::<[f32; 2]>(options), Err(Error::NonFiniteFloat)));
+    assert_eq!(right.try_recv_with_options::<f32>(options).unwrap(), Some(2.5));
+}
+
+#[test]
+fn tcp_loopback() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let accept = thread::spawn(move || TcpTransport::new(listener.accept().unwrap().0));
+    let mut client = TcpTransport::connect(address).unwrap();
+    let mut server = accept.join().unwrap();
+    exchange(&mut client, &mut server);
+
+    drop(client);
+    assert!(matches!(server.recv_bytes(), Err(Error::Io(_))));
+}
+
+#[test]
+fn tcp_partial_frames() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let accept = thread::spawn(move || TcpTransport::new(listener.accept().unwrap().0));
+    let mut raw = TcpStream::connect(address).unwrap();
+    let mut server = accept.join().unwrap();
+
+    // Half a frame is kept until the rest arrives
+    assert_eq!(server.try_recv_bytes().unwrap(), None);
+    raw.write_all(&[3, 0, 0, 0, 1]).unwrap();
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(server.try_recv_bytes().unwrap(), None);
+    raw.write_all(&[2, 3, 1, 0]).unwrap();
+    assert_eq!(server.recv_bytes().unwrap(), vec![1, 2, 3]);
+
+    // Same for a blocking read whose timeout runs out mid-frame
+    server.get_ref().set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+    assert!(matches!(server.recv_bytes(), Err(Error::Io(_))));
+    raw.write_all(&[0, 0, 9]).unwrap();
+    assert_eq!(server.recv_bytes().unwrap(), vec![9]);
+}
+
+#[test]
+fn tcp_rejects_oversized_frames() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let accept = thread::spawn(move || TcpTransport::with_max_frame_size(listener.accept().unwrap().0, 4));
+    let mut client = TcpTransport::connect(address).unwrap();
+    let mut server = accept.join().unwrap();
+
+    assert!(matches!(server.send_bytes(&[0; 5]), Err(Error::FrameTooLarge { size: 5, max: 4 })));
+    client.send_bytes(&[0; 5]).unwrap();
+    assert!(matches!(server.recv_bytes(), Err(Error::FrameTooLarge { size: 5, max: 4 })));
+}
+
+#[test]
+fn udp_loopback() {
+    let (mut left, mut right) = udp_pair();
+    exchange(&mut left, &mut right);
+}
+
+// Sockets straight from `bind`, as `Connection` and `ReliableChannel` get them by default
+#[test]
+fn udp_try_recv_on_a_blocking_socket() {
+    let right_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let left = UdpTransport::bind("127.0.0.1:0", right_socket.local_addr().unwrap()).unwrap();
+    let right = UdpTransport::new(right_socket, left.get_ref().local_addr().unwrap());
+    let mut left = ReliableChannel::new(left);
+    let mut right = ReliableChannel::new(right);
+    assert_eq!(left.get_mut().try_recv_bytes().unwrap(), None);
+
+    left.send(&5u32).unwrap();
+    let started = Instant::now();
+    loop {
+        assert!(started.elapsed() < Duration::from_secs(5), "message never arrived");
+        left.update().unwrap();
+        right.update().unwrap();
+        if let Some(value) = right.recv::<u32>().unwrap() {
+            assert_eq!(value, 5);
+            break;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn udp_ignores_other_senders() {
+    let (mut left, mut right) = udp_pair();
+    let stranger = UdpSocket::bind("127.0.0.1:0").unwrap();
+    stranger.send_to(&[1, 2, 3], right.get_ref().local_addr().unwrap()).unwrap();
+    left.send(&42u32).unwrap();
+    assert_eq!(right.recv::<u32>().unwrap(), 42);
+    assert_eq!(right.peer(), left.get_ref().local_addr().unwrap());
+}
+
+#[test]
+fn udp_rejects_oversized_datagrams() {
+    let (mut left, _right) = udp_pair();
+    let size = MAX_DATAGRAM_SIZE + 1;
+    assert!(matches!(left.send_bytes(&vec![0; size]), Err(Error::FrameTooLarge { size: s, max: MAX_DATAGRAM_SIZE }) if s == size));
+}
+
+// Code written against the trait works with any implementation
+#[test]
+fn dyn_transport() {
+    let (left, mut right) = MemoryTransport::pair();
+    let mut boxed: Box<dyn Transport> = Box::new(left);
+    boxed.send_bytes(&[9, 9]).unwrap();
+    assert_eq!(right.recv_bytes().unwrap(), vec![9, 9]);
+}
@@ -0,0 +1,9 @@
+use byte_transport::ByteDecode;
+
+#[derive(ByteDecode)]
+struct Cache {
+    #[ig(fallback = "make_cache")]
+    entries: Vec<u8>,
+}
+
+fn main() {}
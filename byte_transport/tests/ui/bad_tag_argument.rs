@@ -0,0 +1,9 @@
+use byte_transport::ByteEncode;
+
+#[derive(ByteEncode)]
+enum Message {
+    #[bt(tag = "one")]
+    Ping,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use byte_transport::ByteEncode;
+
+#[derive(ByteEncode)]
+#[bt(tag_type = i128)]
+enum Message {
+    Ping,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use byte_transport::ByteEncode;
+
+#[derive(ByteEncode)]
+#[bt(c_layout)]
+struct Header {
+    kind: u8,
+    value: u32,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use byte_transport::ByteDecode;
+
+#[derive(ByteDecode)]
+enum Message {
+    Batch(u8, #[bt(count = "field_0")] Vec<u8>),
+}
+
+fn main() {}
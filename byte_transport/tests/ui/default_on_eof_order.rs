@@ -0,0 +1,11 @@
+use byte_transport::ByteDecode;
+
+#[derive(ByteDecode)]
+struct Login {
+    user_id: u32,
+    #[bt(default_on_eof)]
+    client_version: u16,
+    session: u64,
+}
+
+fn main() {}
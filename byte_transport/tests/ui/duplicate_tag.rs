@@ -0,0 +1,10 @@
+use byte_transport::ByteEncode;
+
+#[derive(ByteEncode)]
+enum Message {
+    Ping = 1,
+    #[bt(tag = 1)]
+    Pong,
+}
+
+fn main() {}
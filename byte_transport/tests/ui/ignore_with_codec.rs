@@ -0,0 +1,10 @@
+use byte_transport::ByteEncode;
+
+#[derive(ByteEncode)]
+struct Session {
+    #[ig]
+    #[bt(with = "session_codec")]
+    token: u64,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use byte_transport::ByteEncode;
+
+#[derive(ByteEncode)]
+enum Message {
+    Ping(#[bt(checksum = crc32)] u32),
+}
+
+fn main() {}
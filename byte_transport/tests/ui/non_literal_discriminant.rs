@@ -0,0 +1,10 @@
+use byte_transport::ByteEncode;
+
+const BASE: isize = 4;
+
+#[derive(ByteEncode)]
+enum Message {
+    Ping = BASE,
+}
+
+fn main() {}
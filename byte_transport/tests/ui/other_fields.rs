@@ -0,0 +1,11 @@
+use byte_transport::ByteDecode;
+
+#[derive(ByteDecode)]
+#[bt(length_prefixed)]
+enum Message {
+    Ping,
+    #[bt(other)]
+    Unknown(Vec<u8>),
+}
+
+fn main() {}
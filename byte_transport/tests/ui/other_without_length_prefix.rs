@@ -0,0 +1,10 @@
+use byte_transport::ByteDecode;
+
+#[derive(ByteDecode)]
+enum Message {
+    Ping,
+    #[bt(other)]
+    Unknown(u8, Vec<u8>),
+}
+
+fn main() {}
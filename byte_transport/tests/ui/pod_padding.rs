@@ -0,0 +1,11 @@
+use byte_transport::ByteEncode;
+
+#[repr(C)]
+#[derive(ByteEncode, Clone, Copy)]
+#[bt(pod)]
+struct Padded {
+    kind: u8,
+    value: u32,
+}
+
+fn main() {}
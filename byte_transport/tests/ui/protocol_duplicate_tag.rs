@@ -0,0 +1,8 @@
+byte_transport::protocol! {
+    client_to_server {
+        Move(u32) = 1,
+        Chat(String) = 1,
+    }
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+byte_transport::protocol! {
+    client_to_server {
+        Chat(String) = 1,
+    }
+    server_to_client {
+        Chat(String) = 1,
+    }
+}
+
+fn send_to_client(_message: ServerToClient) {}
+
+fn main() {
+    send_to_client(ClientToServer::Chat(String::from("hi")));
+}
@@ -0,0 +1,9 @@
+use byte_transport::ByteDecode;
+
+#[derive(ByteDecode)]
+enum Message {
+    Last = 255,
+    Overflow,
+}
+
+fn main() {}
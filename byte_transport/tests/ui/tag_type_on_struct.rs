@@ -0,0 +1,9 @@
+use byte_transport::ByteDecode;
+
+#[derive(ByteDecode)]
+#[bt(tag_type = u16)]
+struct Position {
+    x: f32,
+}
+
+fn main() {}
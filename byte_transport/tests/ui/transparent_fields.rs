@@ -0,0 +1,7 @@
+use byte_transport::ByteEncode;
+
+#[derive(ByteEncode)]
+#[bt(transparent)]
+struct Position(f32, f32);
+
+fn main() {}
@@ -0,0 +1,9 @@
+use byte_transport::ByteEncode;
+
+#[derive(ByteEncode)]
+union Bits {
+    int: u32,
+    float: f32,
+}
+
+fn main() {}
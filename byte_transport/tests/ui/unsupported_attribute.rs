@@ -0,0 +1,9 @@
+use byte_transport::ByteEncode;
+
+#[derive(ByteEncode)]
+struct Player {
+    #[bt(compress)]
+    name: String,
+}
+
+fn main() {}
@@ -0,0 +1,380 @@
+use syn::{parse_quote, Data, DeriveInput, Expr, ExprRange, Field, Ident, LitByteStr, LitInt, LitStr, Meta, Path, Type, Variant};
+
+// How a field marked with #[ig] gets its value back on decode
+pub enum SkipDefault {
+    Default,
+    Function(Path),
+}
+
+#[derive(Default)]
+pub struct FieldAttrs {
+    pub skip: Option<SkipDefault>,
+    pub encode_with: Option<Path>,
+    pub decode_with: Option<Path>,
+    pub default_on_eof: bool,
+    pub range: Option<ExprRange>,
+    pub finite: bool,
+    pub canonical_nan: bool,
+    // Constant bytes written on encode and checked on decode
+    pub magic: Option<LitByteStr>,
+    // Number of reserved zero bytes, ignored on decode
+    pub pad: Option<usize>,
+    // `Checksum` over the bytes of the struct before this field
+    pub checksum: Option<Path>,
+    // Earlier field holding the number of elements, in place of a length prefix
+    pub count: Option<Expr>,
+    // Earlier field holding this enum's tag, in place of a leading tag
+    pub tag_from: Option<Expr>,
+}
+
+impl FieldAttrs {
+    // Fields whose bytes the derive produces itself rather than the field's value
+    pub fn is_layout(&self) -> bool {
+        self.magic.is_some() || self.pad.is_some() || self.checksum.is_some()
+    }
+
+    // Fields whose encoding depends on an earlier field
+    pub fn is_external(&self) -> bool {
+        self.count.is_some() || self.tag_from.is_some()
+    }
+
+    pub fn from_field(field: &Field) -> syn::Result<Self> {
+        let mut attrs = FieldAttrs::default();
+
+        for attr in &field.attrs {
+            if attr.path().is_ident("bt") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("with") {
+                        // A module providing `simple_encode` and `simple_decode` functions
+                        let module: Path = meta.value()?.parse::<LitStr>()?.parse()?;
+                        attrs.encode_with = Some(parse_quote! { #module::simple_encode });
+                        attrs.decode_with = Some(parse_quote! { #module::simple_decode });
+                        Ok(())
+                    } else if meta.path.is_ident("encode_with") {
+                        attrs.encode_with = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+                        Ok(())
+                    } else if meta.path.is_ident("decode_with") {
+                        attrs.decode_with = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+                        Ok(())
+                    } else if meta.path.is_ident("default_on_eof") {
+                        attrs.default_on_eof = true;
+                        Ok(())
+                    } else if meta.path.is_ident("range") {
+                        attrs.range = Some(meta.value()?.parse()?);
+                        Ok(())
+                    } else if meta.path.is_ident("finite") {
+                        attrs.finite = true;
+                        Ok(())
+                    } else if meta.path.is_ident("canonical_nan") {
+                        attrs.canonical_nan = true;
+                        Ok(())
+                    } else if meta.path.is_ident("magic") {
+                        attrs.magic = Some(meta.value()?.parse()?);
+                        Ok(())
+                    } else if meta.path.is_ident("pad") {
+                        attrs.pad = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+                        Ok(())
+                    } else if meta.path.is_ident("checksum") {
+                        attrs.checksum = Some(meta.value()?.parse()?);
+                        Ok(())
+                    } else if meta.path.is_ident("count") {
+                        attrs.count = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+                        Ok(())
+                    } else if meta.path.is_ident("tag_from") {
+                        attrs.tag_from = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+                        Ok(())
+                    } else {
+                        Err(meta.error("unsupported #[bt] field attribute"))
+                    }
+                })?;
+                continue;
+            }
+            if !attr.path().is_ident("ig") {
+                continue;
+            }
+
+            let mut skip = SkipDefault::Default;
+            // A bare #[ig] falls back to Default, #[ig(default = "path")] calls the given function
+            if let Meta::List(_) = attr.meta {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("default") {
+                        let path: LitStr = meta.value()?.parse()?;
+                        skip = SkipDefault::Function(path.parse()?);
+                        Ok(())
+                    } else {
+                        Err(meta.error("unsupported #[ig] argument, expected `default = \"path\"`"))
+                    }
+                })?;
+            }
+            attrs.skip = Some(skip);
+        }
+
+        if attrs.skip.is_some() && (attrs.encode_with.is_some() || attrs.decode_with.is_some()) {
+            return Err(syn::Error::new_spanned(field, "#[ig] fields are not encoded, so they cannot use a #[bt] codec"));
+        }
+        if attrs.skip.is_some() && (attrs.finite || attrs.canonical_nan) {
+            return Err(syn::Error::new_spanned(field, "#[ig] fields are not encoded, so they cannot use #[bt(finite)] or #[bt(canonical_nan)]"));
+        }
+        if attrs.skip.is_some() && attrs.range.is_some() {
+            return Err(syn::Error::new_spanned(field, "#[ig] fields are never decoded, so they cannot use #[bt(range)]"));
+        }
+        if attrs.skip.is_some() && attrs.default_on_eof {
+            return Err(syn::Error::new_spanned(field, "#[ig] fields are never decoded, so they cannot use #[bt(default_on_eof)]"));
+        }
+        match [attrs.magic.is_some(), attrs.pad.is_some(), attrs.checksum.is_some()].iter().filter(|set| **set).count() {
+            0 => {},
+            1 => if attrs.skip.is_some() || attrs.encode_with.is_some() || attrs.decode_with.is_some() || attrs.default_on_eof
+                || attrs.range.is_some() || attrs.finite || attrs.canonical_nan {
+                return Err(syn::Error::new_spanned(field, "#[bt(magic)], #[bt(pad)] and #[bt(checksum)] fields are written by the derive and cannot take other attributes"));
+            },
+            _ => return Err(syn::Error::new_spanned(field, "only one of #[bt(magic)], #[bt(pad)] or #[bt(checksum)] can be used on a field")),
+        }
+        if attrs.count.is_some() && attrs.tag_from.is_some() {
+            return Err(syn::Error::new_spanned(field, "#[bt(count)] and #[bt(tag_from)] cannot both be used on a field"));
+        }
+        if (attrs.count.is_some() || attrs.tag_from.is_some()) && (attrs.skip.is_some() || attrs.encode_with.is_some()
+            || attrs.decode_with.is_some() || attrs.default_on_eof || attrs.is_layout()) {
+            return Err(syn::Error::new_spanned(field, "#[bt(count)] and #[bt(tag_from)] fields cannot be skipped, use a codec, #[bt(default_on_eof)] or a layout attribute"));
+        }
+
+        Ok(attrs)
+    }
+}
+
+// Wire type used for enum variant tags, set with #[bt(tag_type = ...)]
+#[derive(Clone, Copy, Default)]
+pub enum TagType {
+    #[default]
+    U8,
+    U16,
+    U32,
+    VarInt,
+}
+
+impl TagType {
+    pub fn max(self) -> u64 {
+        match self {
+            TagType::U8 => u8::MAX as u64,
+            TagType::U16 => u16::MAX as u64,
+            TagType::U32 => u32::MAX as u64,
+            TagType::VarInt => u64::MAX,
+        }
+    }
+}
+
+// Byte order declared with #[bt(endian = "...")] on a C layout struct
+#[derive(Clone, Copy)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+pub struct ContainerAttrs {
+    pub tag_type: TagType,
+    // Path generated code uses to reach byte_transport, overridden with #[bt(crate = "path")]
+    pub krate: Path,
+    // Called with the decoded value, returning an `Error` rejects it
+    pub validate: Option<Path>,
+    // Reject non-finite floats anywhere in the decoded value
+    pub finite: bool,
+    // Single-field struct encoded exactly as its field
+    pub transparent: bool,
+    // Proxy types the value is converted through on the wire
+    pub into: Option<Type>,
+    pub from: Option<Type>,
+    pub try_from: Option<Type>,
+    // Every variant payload is preceded by its VarInt length
+    pub length_prefixed: bool,
+    // Option fields share one leading bitmask instead of a flag byte each
+    pub presence_bitmask: bool,
+    // Written as the #[repr(C)] memory layout, padding included
+    pub c_layout: bool,
+    // C layout with no padding, copied in one go when the byte order is native
+    pub pod: bool,
+    // Byte order of a C layout struct, inherited from the enclosing struct when unset
+    pub endian: Option<Endian>,
+    // Expected size and alignment of a C layout struct, checked at compile time
+    pub size: Option<usize>,
+    pub align: Option<usize>,
+}
+
+impl Default for ContainerAttrs {
+    fn default() -> Self {
+        ContainerAttrs {
+            tag_type: TagType::default(),
+            krate: parse_quote! { ::byte_transport },
+            validate: None,
+            finite: false,
+            transparent: false,
+            into: None,
+            from: None,
+            try_from: None,
+            length_prefixed: false,
+            presence_bitmask: false,
+            c_layout: false,
+            pod: false,
+            endian: None,
+            size: None,
+            align: None,
+        }
+    }
+}
+
+impl ContainerAttrs {
+    pub fn from_input(input: &DeriveInput) -> syn::Result<Self> {
+        let mut container = ContainerAttrs::default();
+        let is_enum = matches!(input.data, Data::Enum(_));
+
+        for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("bt")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("tag_type") {
+                    if !is_enum {
+                        return Err(meta.error("`tag_type` only applies to enums"));
+                    }
+                    let tag_type: Ident = meta.value()?.parse()?;
+                    container.tag_type = match tag_type.to_string().as_str() {
+                        "u8" => TagType::U8,
+                        "u16" => TagType::U16,
+                        "u32" => TagType::U32,
+                        "varint" => TagType::VarInt,
+                        _ => return Err(syn::Error::new_spanned(
+                            tag_type,
+                            "unsupported tag_type, expected one of `u8`, `u16`, `u32` or `varint`",
+                        )),
+                    };
+                    Ok(())
+                } else if meta.path.is_ident("crate") {
+                    container.krate = meta.value()?.parse::<LitStr>()?.parse()?;
+                    Ok(())
+                } else if meta.path.is_ident("validate") {
+                    container.validate = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("finite") {
+                    container.finite = true;
+                    Ok(())
+                } else if meta.path.is_ident("length_prefixed") {
+                    if !is_enum {
+                        return Err(meta.error("`length_prefixed` only applies to enums"));
+                    }
+                    container.length_prefixed = true;
+                    Ok(())
+                } else if meta.path.is_ident("presence_bitmask") {
+                    if !matches!(input.data, Data::Struct(_)) {
+                        return Err(meta.error("`presence_bitmask` only applies to structs"));
+                    }
+                    container.presence_bitmask = true;
+                    Ok(())
+                } else if meta.path.is_ident("c_layout") || meta.path.is_ident("pod") {
+                    if !matches!(input.data, Data::Struct(_)) {
+                        return Err(meta.error("`c_layout` and `pod` only apply to structs"));
+                    }
+                    container.c_layout = true;
+                    container.pod |= meta.path.is_ident("pod");
+                    Ok(())
+                } else if meta.path.is_ident("endian") {
+                    let endian: LitStr = meta.value()?.parse()?;
+                    container.endian = match endian.value().as_str() {
+                        "little" => Some(Endian::Little),
+                        "big" => Some(Endian::Big),
+                        _ => return Err(syn::Error::new_spanned(endian, "unsupported endian, expected `little` or `big`")),
+                    };
+                    Ok(())
+                } else if meta.path.is_ident("size") {
+                    container.size = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("align") {
+                    container.align = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("transparent") {
+                    if !matches!(input.data, Data::Struct(_)) {
+                        return Err(meta.error("`transparent` only applies to structs"));
+                    }
+                    container.transparent = true;
+                    Ok(())
+                } else if meta.path.is_ident("into") {
+                    container.into = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("from") {
+                    container.from = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("try_from") {
+                    container.try_from = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported #[bt] container attribute"))
+                }
+            })?;
+        }
+
+        if container.from.is_some() && container.try_from.is_some() {
+            return Err(syn::Error::new_spanned(&input.ident, "`from` and `try_from` cannot both be set"));
+        }
+        if container.transparent && (container.into.is_some() || container.from.is_some() || container.try_from.is_some()) {
+            return Err(syn::Error::new_spanned(&input.ident, "`transparent` cannot be combined with `into`, `from` or `try_from`"));
+        }
+        if !container.c_layout && (container.endian.is_some() || container.size.is_some() || container.align.is_some()) {
+            return Err(syn::Error::new_spanned(&input.ident, "`endian`, `size` and `align` require `c_layout` or `pod`"));
+        }
+        if container.c_layout {
+            if container.transparent || container.presence_bitmask || container.finite
+                || container.into.is_some() || container.from.is_some() || container.try_from.is_some() {
+                return Err(syn::Error::new_spanned(&input.ident, "`c_layout` and `pod` cannot be combined with `transparent`, `presence_bitmask`, `finite` or a proxy"));
+            }
+            if !is_repr_c(input)? {
+                return Err(syn::Error::new_spanned(&input.ident, "`c_layout` and `pod` require #[repr(C)]"));
+            }
+        }
+        if container.transparent && container.presence_bitmask {
+            return Err(syn::Error::new_spanned(&input.ident, "`transparent` cannot be combined with `presence_bitmask`"));
+        }
+
+        Ok(container)
+    }
+}
+
+// Whether the type is declared #[repr(C)], possibly alongside `packed` or `align`
+fn is_repr_c(input: &DeriveInput) -> syn::Result<bool> {
+    let mut repr_c = false;
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("repr")) {
+        attr.parse_nested_meta(|meta| {
+            repr_c |= meta.path.is_ident("C");
+            // Skip over arguments such as `align(8)` or `packed(2)`
+            if meta.input.peek(syn::token::Paren) {
+                let _content;
+                syn::parenthesized!(_content in meta.input);
+            }
+            Ok(())
+        })?;
+    }
+    Ok(repr_c)
+}
+
+#[derive(Default)]
+pub struct VariantAttrs {
+    pub tag: Option<u64>,
+    // Catch-all for tags this build doesn't know about
+    pub other: bool,
+}
+
+impl VariantAttrs {
+    pub fn from_variant(variant: &Variant) -> syn::Result<Self> {
+        let mut variant_attrs = VariantAttrs::default();
+
+        for attr in variant.attrs.iter().filter(|attr| attr.path().is_ident("bt")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("tag") {
+                    let tag: LitInt = meta.value()?.parse()?;
+                    variant_attrs.tag = Some(tag.base10_parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("other") {
+                    variant_attrs.other = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported #[bt] variant attribute"))
+                }
+            })?;
+        }
+
+        Ok(variant_attrs)
+    }
+}
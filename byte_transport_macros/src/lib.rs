@@ -1,172 +1,1049 @@
 extern crate proc_macro;
 use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
 use quote::{quote, ToTokens};
-use syn::{parse_macro_input, Data, DeriveInput, Field, Fields};
+use syn::{parse_macro_input, Data, DataEnum, DeriveInput, Expr, Field, Fields, GenericArgument, Lit, Path, PathArguments, Type, Variant};
+
+mod attr;
+mod protocol;
+
+use attr::{ContainerAttrs, Endian, FieldAttrs, SkipDefault, TagType, VariantAttrs};
+
+// Local binding used for a field while decoding; tuple fields become `field_{i}`
+fn field_binding(index: usize, field: &Field) -> syn::Ident {
+    match field.ident {
+        Some(ref ident) => ident.clone(),
+        None => syn::Ident::new(&format!("field_{index}"), proc_macro2::Span::call_site()),
+    }
+}
+
+// Local or parameter only generated code refers to. The mixed-site span keeps it apart from field
+// bindings of the same name, which would otherwise shadow it or be shadowed by it.
+fn internal_ident(name: &str) -> syn::Ident {
+    syn::Ident::new(name, proc_macro2::Span::mixed_site())
+}
+
+// Parses the attributes of every field up front so errors surface before codegen
+fn field_attrs(fields: &Fields) -> syn::Result<Vec<FieldAttrs>> {
+    fields.iter().map(FieldAttrs::from_field).collect()
+}
+
+// Statement writing a field, through its #[bt(encode_with)] function when one is given
+fn encode_value(krate: &Path, attrs: &FieldAttrs, value: TokenStream2) -> TokenStream2 {
+    let bytes = internal_ident("bytes");
+    let value = match attrs.canonical_nan {
+        true => quote! { &#krate::CanonicalNan::canonical_nan(#value) },
+        false => value,
+    };
+    match attrs.encode_with {
+        Some(ref path) => quote! { #path(#value, #bytes)?; },
+        None => quote! { #krate::ByteEncode::simple_encode(#value, #bytes)?; },
+    }
+}
+
+// Runs a decode expression with `DecoderOptions::finite_floats` switched on, restoring it afterwards
+fn with_finite_floats(result: TokenStream2) -> TokenStream2 {
+    let decoder = internal_ident("decoder");
+    quote! {
+        {
+            let previous_finite_floats = ::core::mem::replace(&mut #decoder.options.finite_floats, true);
+            let result = #result;
+            #decoder.options.finite_floats = previous_finite_floats;
+            result
+        }
+    }
+}
+
+// #[bt(finite)] on the container applies to every float read while decoding it
+fn finite_body(krate: &Path, container: &ContainerAttrs, body: TokenStream2) -> TokenStream2 {
+    let decoder = internal_ident("decoder");
+    match container.finite {
+        true => with_finite_floats(quote! {
+            (|#decoder: &mut #krate::Decoder| -> ::core::result::Result<Self, #krate::Error> { #body })(#decoder)
+        }),
+        false => quote! { { #body } },
+    }
+}
+
+// Expression producing a field's value on decode; skipped fields never touch the decoder
+fn decode_value(krate: &Path, attrs: &FieldAttrs) -> TokenStream2 {
+    let decoder = internal_ident("decoder");
+    let result = match (&attrs.skip, &attrs.decode_with) {
+        (Some(SkipDefault::Default), _) => return quote! { ::core::default::Default::default() },
+        (Some(SkipDefault::Function(path)), _) => return quote! { #path() },
+        (None, Some(path)) => quote! { #path(#decoder) },
+        (None, None) => quote! { #krate::ByteDecode::simple_decode(#decoder) },
+    };
+    let value = match attrs.finite {
+        true => {
+            let result = with_finite_floats(result);
+            quote! { #result? }
+        },
+        false => quote! { #result? },
+    };
+    // Only a clean end of input falls back to Default, a partially written field is still an error
+    match attrs.default_on_eof {
+        true => quote! {
+            if #decoder.is_at_end() { ::core::default::Default::default() } else { #value }
+        },
+        false => value,
+    }
+}
+
+// Name reported in `Error::Validation`, the index for tuple fields
+fn field_label(index: usize, field: &Field) -> String {
+    match field.ident {
+        Some(ref ident) => ident.to_string(),
+        None => index.to_string(),
+    }
+}
+
+// Rejects a decoded field outside its #[bt(range = ...)] before the rest of the value is read
+fn range_check(krate: &Path, attrs: &FieldAttrs, binding: &syn::Ident, label: String) -> TokenStream2 {
+    let Some(ref range) = attrs.range else {
+        return quote! {};
+    };
+    let reason = format!("value is outside the range {}", range.to_token_stream());
+    quote! {
+        if !(#range).contains(&#binding) {
+            return ::core::result::Result::Err(#krate::Error::Validation {
+                field: ::std::string::String::from(#label),
+                reason: ::std::string::String::from(#reason),
+            });
+        }
+    }
+}
+
+// Type implementing `Checksum`, with `crc32`, `sum8` and `xor8` naming the built-in ones
+fn checksum_type(krate: &Path, checksum: &Path) -> TokenStream2 {
+    match checksum.get_ident().map(|ident| ident.to_string()).as_deref() {
+        Some("crc32") => quote! { #krate::Crc32 },
+        Some("sum8") => quote! { #krate::Sum8 },
+        Some("xor8") => quote! { #krate::Xor8 },
+        _ => quote! { #checksum },
+    }
+}
+
+// Statement writing a #[bt(magic)], #[bt(pad)] or #[bt(checksum)] field, which ignore the field's value
+fn layout_encode(krate: &Path, attrs: &FieldAttrs) -> Option<TokenStream2> {
+    let bytes = internal_ident("bytes");
+    if let Some(ref magic) = attrs.magic {
+        return Some(quote! { #bytes.extend_from_slice(#magic); });
+    }
+    if let Some(pad) = attrs.pad {
+        return Some(quote! { #bytes.extend_from_slice(&[0u8; #pad]); });
+    }
+    let checksum = checksum_type(krate, attrs.checksum.as_ref()?);
+    let checksum_start = internal_ident("checksum_start");
+    Some(quote! {
+        #krate::ByteEncode::simple_encode(&<#checksum as #krate::Checksum>::compute(&#bytes[#checksum_start..]), #bytes)?;
+    })
+}
+
+// Expression decoding a #[bt(magic)], #[bt(pad)] or #[bt(checksum)] field and verifying it
+fn layout_decode(krate: &Path, attrs: &FieldAttrs, label: String) -> Option<TokenStream2> {
+    let decoder = internal_ident("decoder");
+    if let Some(ref magic) = attrs.magic {
+        let len = magic.value().len();
+        return Some(quote! {
+            {
+                let found = #decoder.read_array::<#len>()?;
+                if &found != #magic {
+                    return ::core::result::Result::Err(#krate::Error::MagicMismatch {
+                        field: ::std::string::String::from(#label),
+                        expected: ::std::vec::Vec::from(&#magic[..]),
+                        found: ::std::vec::Vec::from(&found[..]),
+                    });
+                }
+                found
+            }
+        });
+    }
+    if let Some(pad) = attrs.pad {
+        return Some(quote! {
+            {
+                #decoder.read_slice(#pad)?;
+                ::core::default::Default::default()
+            }
+        });
+    }
+    let checksum = checksum_type(krate, attrs.checksum.as_ref()?);
+    let checksum_start = internal_ident("checksum_start");
+    Some(quote! {
+        {
+            let computed = <#checksum as #krate::Checksum>::compute(&#decoder.bytes[#checksum_start..#decoder.index]);
+            let stored = <<#checksum as #krate::Checksum>::Output as #krate::ByteDecode>::simple_decode(#decoder)?;
+            if stored != computed {
+                return ::core::result::Result::Err(#krate::Error::ChecksumMismatch {
+                    field: ::std::string::String::from(#label),
+                    stored: ::core::convert::Into::into(stored),
+                    computed: ::core::convert::Into::into(computed),
+                });
+            }
+            stored
+        }
+    })
+}
+
+// Checksums are taken from the start of a struct and other fields are referred to by name,
+// neither of which enum variants support
+fn check_struct_only(fields: &Fields, attrs: &[FieldAttrs]) -> syn::Result<()> {
+    for (field, attrs) in fields.iter().zip(attrs) {
+        if attrs.is_layout() {
+            return Err(syn::Error::new_spanned(field, "#[bt(magic)], #[bt(pad)] and #[bt(checksum)] are only supported on struct fields"));
+        }
+        if attrs.is_external() {
+            return Err(syn::Error::new_spanned(field, "#[bt(count)] and #[bt(tag_from)] are only supported on struct fields"));
+        }
+    }
+    Ok(())
+}
+
+// Statement writing a #[bt(count)] or #[bt(tag_from)] field after checking it agrees with the field it refers to.
+// `earlier` binds the fields before it by reference, matching the names they have on decode.
+fn external_encode(krate: &Path, attrs: &FieldAttrs, earlier: &[TokenStream2], member: &TokenStream2, label: String) -> Option<TokenStream2> {
+    let bytes = internal_ident("bytes");
+    if let Some(ref count) = attrs.count {
+        let encode = encode_value(krate, attrs, quote! { item });
+        return Some(quote! {
+            {
+                let count = {
+                    use #krate::FieldInteger as _;
+                    #(#earlier)*
+                    (#count).to_count()?
+                };
+                if count != self.#member.len() {
+                    return ::core::result::Result::Err(#krate::Error::Validation {
+                        field: ::std::string::String::from(#label),
+                        reason: ::std::format!("has {} elements but its count field says {}", self.#member.len(), count),
+                    });
+                }
+                for item in &self.#member {
+                    #encode
+                }
+            }
+        });
+    }
+    let tag_from = attrs.tag_from.as_ref()?;
+    Some(quote! {
+        {
+            let tag = {
+                use #krate::FieldInteger as _;
+                #(#earlier)*
+                (#tag_from).to_u64()?
+            };
+            let variant_tag = #krate::EncodeVariant::variant_tag(&self.#member);
+            if tag != variant_tag {
+                return ::core::result::Result::Err(#krate::Error::Validation {
+                    field: ::std::string::String::from(#label),
+                    reason: ::std::format!("has variant tag {} but its tag field says {}", variant_tag, tag),
+                });
+            }
+            #krate::EncodeVariant::encode_variant(&self.#member, #bytes)?;
+        }
+    })
+}
+
+// Expression decoding a #[bt(count)] or #[bt(tag_from)] field from the earlier fields already bound
+fn external_decode(krate: &Path, attrs: &FieldAttrs, field_type: &Type) -> Option<TokenStream2> {
+    let decoder = internal_ident("decoder");
+    if let Some(ref count) = attrs.count {
+        let element = match attrs.finite {
+            true => with_finite_floats(quote! { #krate::ByteDecode::simple_decode(#decoder) }),
+            false => quote! { #krate::ByteDecode::simple_decode(#decoder) },
+        };
+        return Some(quote! {
+            {
+                use #krate::FieldInteger as _;
+                let count = (#count).to_count()?;
+                (0..count).map(|_| #element).collect::<::core::result::Result<#field_type, #krate::Error>>()?
+            }
+        });
+    }
+    let tag_from = attrs.tag_from.as_ref()?;
+    Some(quote! {
+        {
+            use #krate::FieldInteger as _;
+            let tag = (#tag_from).to_u64()?;
+            <#field_type as #krate::DecodeVariant>::decode_variant(tag, #decoder)?
+        }
+    })
+}
+
+// #[bt(transparent)] structs must have exactly one field on the wire
+fn check_transparent(fields: &Fields, attrs: &[FieldAttrs]) -> syn::Result<()> {
+    match attrs.iter().filter(|attrs| attrs.skip.is_none()).count() {
+        1 => Ok(()),
+        _ => Err(syn::Error::new_spanned(fields, "#[bt(transparent)] requires exactly one field that is not #[ig]")),
+    }
+}
+
+// #[bt(default_on_eof)] fields must all come after every field that is always present
+fn check_default_on_eof(fields: &Fields, attrs: &[FieldAttrs]) -> syn::Result<()> {
+    let mut seen_default_on_eof = false;
+    for (field, attrs) in fields.iter().zip(attrs) {
+        if attrs.default_on_eof {
+            seen_default_on_eof = true;
+        } else if seen_default_on_eof && attrs.skip.is_none() {
+            return Err(syn::Error::new_spanned(field, "fields after a #[bt(default_on_eof)] field must also be #[bt(default_on_eof)]"));
+        }
+    }
+    Ok(())
+}
+
+// Inner type of an `Option<T>` field, matched on the last path segment
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(ref type_path) = *ty else {
+        return None;
+    };
+    let segment = type_path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    match segment.arguments {
+        PathArguments::AngleBracketed(ref arguments) if arguments.args.len() == 1 => match arguments.args.first() {
+            Some(GenericArgument::Type(inner)) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Bit of each field in the #[bt(presence_bitmask)] mask; fields with a codec keep their own encoding
+fn presence_bits(fields: &Fields, attrs: &[FieldAttrs], container: &ContainerAttrs) -> syn::Result<Vec<Option<usize>>> {
+    let mut next_bit = 0;
+    let mut bits = Vec::new();
+    for (field, attrs) in fields.iter().zip(attrs) {
+        let in_mask = container.presence_bitmask
+            && attrs.skip.is_none()
+            && attrs.encode_with.is_none()
+            && attrs.decode_with.is_none()
+            && !attrs.is_layout()
+            && !attrs.is_external()
+            && option_inner(&field.ty).is_some();
+        if !in_mask {
+            bits.push(None);
+            continue;
+        }
+        if attrs.default_on_eof {
+            return Err(syn::Error::new_spanned(field, "#[bt(default_on_eof)] cannot be used on fields in the presence bitmask"));
+        }
+        bits.push(Some(next_bit));
+        next_bit += 1;
+    }
+    Ok(bits)
+}
+
+// Number of bytes the presence bitmask takes on the wire
+fn presence_len(bits: &[Option<usize>]) -> usize {
+    bits.iter().flatten().count().div_ceil(8)
+}
+
+// Index into the mask and the bit within that byte
+fn presence_position(bit: usize) -> (usize, u8) {
+    (bit / 8, 1u8 << (bit % 8))
+}
+
+// Wire tag of every variant, from #[bt(tag = N)], an explicit discriminant, or the previous tag + 1.
+// The #[bt(other)] catch-all has no tag of its own and gets `None`.
+fn enum_tags(data_enum: &DataEnum, container: &ContainerAttrs) -> syn::Result<Vec<Option<u64>>> {
+    let tag_type = container.tag_type;
+    let mut tags: Vec<u64> = Vec::new();
+    let mut variant_tags: Vec<Option<u64>> = Vec::new();
+    let mut seen_other = false;
+
+    for variant in &data_enum.variants {
+        let variant_attrs = VariantAttrs::from_variant(variant)?;
+        if variant_attrs.other {
+            check_other_variant(variant, &variant_attrs, container, seen_other)?;
+            seen_other = true;
+            variant_tags.push(None);
+            continue;
+        }
+        let tag = match (variant_attrs.tag, &variant.discriminant) {
+            (Some(tag), _) => tag,
+            (None, Some((_, Expr::Lit(expr_lit)))) => match expr_lit.lit {
+                Lit::Int(ref lit_int) => lit_int.base10_parse()?,
+                _ => return Err(syn::Error::new_spanned(expr_lit, "enum discriminant must be an integer literal")),
+            },
+            (None, Some((_, expr))) => {
+                return Err(syn::Error::new_spanned(expr, "enum discriminant must be a non-negative integer literal, use #[bt(tag = N)] instead"));
+            },
+            (None, None) => match tags.last() {
+                Some(previous) => previous.checked_add(1)
+                    .ok_or_else(|| syn::Error::new_spanned(variant, "enum tag overflows u64"))?,
+                None => 0,
+            },
+        };
+
+        if tag > tag_type.max() {
+            return Err(syn::Error::new_spanned(
+                variant,
+                format!("enum tag {tag} does not fit in the tag type, the maximum is {}", tag_type.max()),
+            ));
+        }
+        if tags.contains(&tag) {
+            return Err(syn::Error::new_spanned(variant, format!("duplicate enum tag {tag}")));
+        }
+        tags.push(tag);
+        variant_tags.push(Some(tag));
+    }
+
+    Ok(variant_tags)
+}
+
+// The catch-all holds the unknown tag and the raw payload, which only a length prefix can delimit
+fn check_other_variant(variant: &Variant, variant_attrs: &VariantAttrs, container: &ContainerAttrs, seen_other: bool) -> syn::Result<()> {
+    if !container.length_prefixed {
+        return Err(syn::Error::new_spanned(variant, "#[bt(other)] requires #[bt(length_prefixed)] on the enum"));
+    }
+    if seen_other {
+        return Err(syn::Error::new_spanned(variant, "only one variant can be #[bt(other)]"));
+    }
+    if variant_attrs.tag.is_some() || variant.discriminant.is_some() {
+        return Err(syn::Error::new_spanned(variant, "the #[bt(other)] variant cannot have a tag"));
+    }
+    if matches!(variant.fields, Fields::Unit) || variant.fields.len() != 2 {
+        return Err(syn::Error::new_spanned(variant, "the #[bt(other)] variant must have two fields, the tag and a `Vec<u8>` payload"));
+    }
+    Ok(())
+}
+
+// Pattern and constructor for the #[bt(other)] variant, whose fields are the tag and the payload
+fn other_variant_parts(variant: &Variant) -> (TokenStream2, TokenStream2) {
+    let variant_name = &variant.ident;
+    let mut fields = variant.fields.iter().enumerate().map(|(i, field)| field_binding(i, field));
+    let (tag, payload) = (fields.next(), fields.next());
+    match variant.fields {
+        Fields::Named(_) => (
+            quote! { Self::#variant_name { ref #tag, ref #payload } },
+            quote! { Self::#variant_name { #tag: variant_idx, #payload: payload } },
+        ),
+        _ => (
+            quote! { Self::#variant_name(ref #tag, ref #payload) },
+            quote! { Self::#variant_name(variant_idx, payload) },
+        ),
+    }
+}
+
+// Body of an encode arm; #[bt(length_prefixed)] writes the fields to a scratch buffer to measure them
+fn variant_encode_body(krate: &Path, container: &ContainerAttrs, encode_tag: TokenStream2, field_encodes: TokenStream2) -> TokenStream2 {
+    let bytes = internal_ident("bytes");
+    if !container.length_prefixed {
+        return quote! {
+            #encode_tag
+            #field_encodes
+            ::core::result::Result::Ok(())
+        };
+    }
+    let payload = internal_ident("payload");
+    let fill_payload = match field_encodes.is_empty() {
+        true => quote! {},
+        false => quote! {
+            {
+                let #bytes = &mut #payload;
+                #field_encodes
+            }
+        },
+    };
+    quote! {
+        #encode_tag
+        #[allow(unused_mut)]
+        let mut #payload: ::std::vec::Vec<u8> = ::std::vec::Vec::new();
+        #fill_payload
+        #krate::ByteEncode::simple_encode(&#krate::VarInt(#payload.len() as u64), #bytes)?;
+        #bytes.extend_from_slice(&#payload);
+        ::core::result::Result::Ok(())
+    }
+}
+
+// Integer type a tag is matched as
+fn tag_integer(tag_type: TagType) -> TokenStream2 {
+    match tag_type {
+        TagType::U8 => quote! { u8 },
+        TagType::U16 => quote! { u16 },
+        TagType::U32 => quote! { u32 },
+        TagType::VarInt => quote! { u64 },
+    }
+}
+
+// Typed literal for a tag so it matches the integer read back by `tag_decode`
+fn tag_literal(tag_type: TagType, tag: u64) -> TokenStream2 {
+    let suffix = match tag_type {
+        TagType::U8 => "u8",
+        TagType::U16 => "u16",
+        TagType::U32 => "u32",
+        TagType::VarInt => "u64",
+    };
+    syn::LitInt::new(&format!("{tag}{suffix}"), proc_macro2::Span::call_site()).into_token_stream()
+}
+
+// Statement writing a tag, given as a literal or as the value held by the #[bt(other)] variant
+fn tag_encode(krate: &Path, tag_type: TagType, tag: TokenStream2) -> TokenStream2 {
+    let bytes = internal_ident("bytes");
+    match tag_type {
+        TagType::VarInt => quote! { #krate::ByteEncode::simple_encode(&#krate::VarInt(#tag), #bytes)?; },
+        _ => quote! { #krate::ByteEncode::simple_encode(&#tag, #bytes)?; },
+    }
+}
+
+fn tag_decode(krate: &Path, tag_type: TagType) -> TokenStream2 {
+    let decoder = internal_ident("decoder");
+    match tag_type {
+        TagType::U8 => quote! { <u8 as #krate::ByteDecode>::simple_decode(#decoder)? },
+        TagType::U16 => quote! { <u16 as #krate::ByteDecode>::simple_decode(#decoder)? },
+        TagType::U32 => quote! { <u32 as #krate::ByteDecode>::simple_decode(#decoder)? },
+        TagType::VarInt => quote! { <#krate::VarInt as #krate::ByteDecode>::simple_decode(#decoder)?.0 },
+    }
+}
+
+// Byte order used by a C layout struct at the top level, where nothing encloses it
+fn c_layout_endian(krate: &Path, container: &ContainerAttrs) -> TokenStream2 {
+    match container.endian {
+        Some(Endian::Big) => quote! { #krate::Endian::Big },
+        Some(Endian::Little) | None => quote! { #krate::Endian::Little },
+    }
+}
+
+// C layout fields are written exactly as declared, so per-field attributes have nothing to act on
+fn check_c_layout_fields(fields: &Fields) -> syn::Result<()> {
+    for field in fields {
+        if let Some(attr) = field.attrs.iter().find(|attr| attr.path().is_ident("bt") || attr.path().is_ident("ig")) {
+            return Err(syn::Error::new_spanned(attr, "fields of a `c_layout` or `pod` struct cannot have #[bt] or #[ig] attributes"));
+        }
+    }
+    Ok(())
+}
+
+// `CLayout` and `ByteEncode` impls for #[bt(c_layout)], plus the compile time layout checks.
+// The offsets come from `offset_of!`, so the padding written is the padding the compiler chose.
+fn c_layout_encode(name: &syn::Ident, fields: &Fields, container: &ContainerAttrs) -> syn::Result<TokenStream2> {
+    let bytes = internal_ident("bytes");
+    check_c_layout_fields(fields)?;
+    let krate = &container.krate;
+    let members: Vec<TokenStream2> = fields.iter().enumerate().map(|(i, f)| match f.ident {
+        Some(ref ident) => quote! { #ident },
+        None => syn::Index::from(i).into_token_stream(),
+    }).collect();
+    let bindings: Vec<syn::Ident> = fields.iter().enumerate().map(|(i, f)| field_binding(i, f)).collect();
+    let types: Vec<&Type> = fields.iter().map(|f| &f.ty).collect();
+    let construct = match fields {
+        Fields::Named(_) => quote! { Self { #(#bindings),* } },
+        Fields::Unnamed(_) => quote! { Self(#(#bindings),*) },
+        Fields::Unit => quote! { Self },
+    };
+
+    let declared_endian = match container.endian {
+        Some(_) => {
+            let endian = c_layout_endian(krate, container);
+            quote! {
+                let _ = endian;
+                let endian = #endian;
+            }
+        },
+        None => quote! {},
+    };
+    let (fast_write, fast_read) = match container.pod {
+        true => (
+            quote! {
+                if endian == #krate::Endian::NATIVE {
+                    #bytes.extend_from_slice(#krate::Pod::as_bytes(self));
+                    return;
+                }
+            },
+            quote! {
+                if endian == #krate::Endian::NATIVE {
+                    return #krate::Pod::from_bytes(#bytes);
+                }
+            },
+        ),
+        false => (quote! {}, quote! {}),
+    };
+
+    let mut checks = Vec::new();
+    if let Some(size) = container.size {
+        let message = format!("{name} does not have the declared size of {size} bytes");
+        checks.push(quote! { ::core::assert!(::core::mem::size_of::<#name>() == #size, #message); });
+    }
+    if let Some(align) = container.align {
+        let message = format!("{name} does not have the declared alignment of {align} bytes");
+        checks.push(quote! { ::core::assert!(::core::mem::align_of::<#name>() == #align, #message); });
+    }
+    let pod_impl = match container.pod {
+        true => {
+            let message = format!("{name} has padding bytes, so it cannot be #[bt(pod)]");
+            checks.push(quote! {
+                ::core::assert!(::core::mem::size_of::<#name>() == 0 #(+ ::core::mem::size_of::<#types>())*, #message);
+            });
+            quote! {
+                // Every field is Pod and the size check above rules out padding
+                unsafe impl #krate::Pod for #name {}
+
+                const _: fn() = || {
+                    fn assert_pod<T: #krate::Pod>() {}
+                    #(assert_pod::<#types>();)*
+                };
+            }
+        },
+        false => quote! {},
+    };
+    let endian = c_layout_endian(krate, container);
+
+    Ok(quote! {
+        impl #krate::CLayout for #name {
+            fn write_c(&self, #bytes: &mut ::std::vec::Vec<u8>, endian: #krate::Endian) {
+                #declared_endian
+                #fast_write
+                let start = #bytes.len();
+                #(
+                    #bytes.resize(start + ::core::mem::offset_of!(Self, #members), 0u8);
+                    #krate::CLayout::write_c(&self.#members, #bytes, endian);
+                )*
+                #bytes.resize(start + ::core::mem::size_of::<Self>(), 0u8);
+            }
+
+            fn read_c(#bytes: &[u8], endian: #krate::Endian) -> ::core::result::Result<Self, #krate::Error> {
+                #declared_endian
+                if #bytes.len() != ::core::mem::size_of::<Self>() {
+                    return ::core::result::Result::Err(#krate::Error::UnexpectedEof {
+                        needed: ::core::mem::size_of::<Self>(),
+                        remaining: #bytes.len(),
+                    });
+                }
+                #fast_read
+                #(
+                    let #bindings = <#types as #krate::CLayout>::read_c(
+                        &#bytes[::core::mem::offset_of!(Self, #members)..][..::core::mem::size_of::<#types>()],
+                        endian,
+                    )?;
+                )*
+                ::core::result::Result::Ok(#construct)
+            }
+        }
+
+        const _: () = {
+            #(#checks)*
+        };
+
+        #pod_impl
+
+        impl #krate::ByteEncode for #name {
+            fn simple_encode(&self, #bytes: &mut ::std::vec::Vec<u8>) -> ::core::result::Result<(), #krate::Error> {
+                #krate::CLayout::write_c(self, #bytes, #endian);
+                ::core::result::Result::Ok(())
+            }
+        }
+    })
+}
 
 // Derive macro for ByteEncode
-#[proc_macro_derive(ByteEncode, attributes(ig))]
+#[proc_macro_derive(ByteEncode, attributes(ig, bt))]
 pub fn derive_byte_encode(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
 
+    expand_byte_encode(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_byte_encode(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let bytes = internal_ident("bytes");
     let name = &input.ident;
+    let container = ContainerAttrs::from_input(input)?;
+    let krate = &container.krate;
+
+    // #[bt(into = "Wire")] encodes a converted clone instead of the value's own fields
+    if let Some(ref wire) = container.into {
+        return Ok(quote! {
+            impl #krate::ByteEncode for #name {
+                fn simple_encode(&self, #bytes: &mut ::std::vec::Vec<u8>) -> ::core::result::Result<(), #krate::Error> {
+                    let wire: #wire = ::core::convert::Into::into(::core::clone::Clone::clone(self));
+                    #krate::ByteEncode::simple_encode(&wire, #bytes)
+                }
+            }
+        });
+    }
 
     let encode_impl = match input.data {
+        // The `CLayout` impl comes from this derive, ByteDecode only reads through it
+        Data::Struct(ref data) if container.c_layout => c_layout_encode(name, &data.fields, &container)?,
         Data::Struct(ref data) => {
-            let field_encodes = data.fields.iter().map(|f| {
-                let name = &f.ident;
+            let attrs = field_attrs(&data.fields)?;
+            if container.transparent {
+                check_transparent(&data.fields, &attrs)?;
+            }
+            let bits = presence_bits(&data.fields, &attrs, &container)?;
+            // Tuple struct fields have no ident, so they are accessed by index
+            let members: Vec<TokenStream2> = data.fields.iter().enumerate().map(|(i, f)| match f.ident {
+                Some(ref ident) => quote! { #ident },
+                None => {
+                    let index = syn::Index::from(i);
+                    quote! { #index }
+                }
+            }).collect();
+            let presence = internal_ident("presence");
+            let presence_mask = match presence_len(&bits) {
+                0 => quote! {},
+                len => {
+                    let set_bits = members.iter().zip(&bits).filter_map(|(member, bit)| {
+                        let (byte, mask) = presence_position((*bit)?);
+                        Some(quote! {
+                            if ::core::option::Option::is_some(&self.#member) {
+                                #presence[#byte] |= #mask;
+                            }
+                        })
+                    });
+                    quote! {
+                        let mut #presence = [0u8; #len];
+                        #(#set_bits)*
+                        #bytes.extend_from_slice(&#presence);
+                    }
+                },
+            };
+            // Earlier fields bound by reference for #[bt(count)] and #[bt(tag_from)] expressions
+            let earlier_bindings: Vec<TokenStream2> = data.fields.iter().zip(&members).enumerate().map(|(i, (f, member))| {
+                let binding = field_binding(i, f);
                 quote! {
-                    ByteEncode::simple_encode(&self.#name, bytes)?;
+                    #[allow(unused_variables)]
+                    let #binding = &self.#member;
                 }
-            });
+            }).collect();
+            let field_encodes = members.iter().zip(&attrs).zip(&bits).enumerate()
+                .filter(|(_, ((_, attrs), _))| attrs.skip.is_none())
+                .map(|(i, ((member, attrs), bit))| match bit {
+                    // Only present values are written, the mask already says which ones
+                    Some(_) => {
+                        let encode = encode_value(krate, attrs, quote! { value });
+                        quote! {
+                            if let ::core::option::Option::Some(ref value) = self.#member {
+                                #encode
+                            }
+                        }
+                    },
+                    None => layout_encode(krate, attrs)
+                        .or_else(|| {
+                            let label = field_label(i, data.fields.iter().nth(i).unwrap());
+                            external_encode(krate, attrs, &earlier_bindings[..i], member, label)
+                        })
+                        .unwrap_or_else(|| encode_value(krate, attrs, quote! { &self.#member })),
+                });
+            let checksum_start = match attrs.iter().any(|attrs| attrs.checksum.is_some()) {
+                true => {
+                    let checksum_start = internal_ident("checksum_start");
+                    quote! { let #checksum_start = #bytes.len(); }
+                },
+                false => quote! {},
+            };
+            // Unit structs have nothing to write
+            let unused_bytes = match attrs.iter().all(|attrs| attrs.skip.is_some()) {
+                true => quote! { let _ = #bytes; },
+                false => quote! {},
+            };
             quote! {
-                impl ByteEncode for #name {
-                    fn simple_encode(&self, bytes: &mut Vec<u8>) -> Result<(), byte_transport::Error> {
+                impl #krate::ByteEncode for #name {
+                    fn simple_encode(&self, #bytes: &mut ::std::vec::Vec<u8>) -> ::core::result::Result<(), #krate::Error> {
+                        #unused_bytes
+                        #checksum_start
+                        #presence_mask
                         #(#field_encodes)*
-                        Ok(())
+                        ::core::result::Result::Ok(())
                     }
                 }
             }
         },
         Data::Enum(ref data_enum) => {
-            let variant_encodes = data_enum.variants.iter().enumerate().map(|(idx, variant)| {
+            let tags = enum_tags(data_enum, &container)?;
+            let mut variant_encodes = Vec::new();
+            let mut untagged_encodes = Vec::new();
+            let mut variant_tags = Vec::new();
+            // Without a length prefix an enum of unit variants writes nothing once the tag is external
+            let mut untagged_writes = container.length_prefixed;
+            for (variant, tag) in data_enum.variants.iter().zip(tags) {
                 let variant_name = &variant.ident;
-                let idx = idx as u8;  // Enum variant index as a `u8`
-                match variant.fields {
-                    Fields::Unit => quote! {
-                        Self::#variant_name => {
-                            bytes.push(#idx);
-                            Ok(())
+                let Some(tag) = tag else {
+                    // The catch-all writes back the tag and payload it captured, forwarding it unchanged
+                    let (pattern, _) = other_variant_parts(variant);
+                    let tag = field_binding(0, variant.fields.iter().next().unwrap());
+                    let payload = field_binding(1, variant.fields.iter().nth(1).unwrap());
+                    let encode_tag = tag_encode(krate, container.tag_type, quote! { *#tag });
+                    let encode_payload = quote! {
+                        #krate::ByteEncode::simple_encode(&#krate::VarInt(#payload.len() as u64), #bytes)?;
+                        #bytes.extend_from_slice(#payload);
+                        ::core::result::Result::Ok(())
+                    };
+                    variant_encodes.push(quote! {
+                        #pattern => {
+                            #encode_tag
+                            #encode_payload
                         }
-                    },
+                    });
+                    untagged_encodes.push(quote! {
+                        #pattern => {
+                            #encode_payload
+                        }
+                    });
+                    let tag_pattern = match variant.fields {
+                        Fields::Named(_) => quote! { Self::#variant_name { ref #tag, .. } },
+                        _ => quote! { Self::#variant_name(ref #tag, _) },
+                    };
+                    variant_tags.push(quote! { #tag_pattern => ::core::convert::From::from(*#tag) });
+                    continue;
+                };
+                let encode_tag = tag_encode(krate, container.tag_type, tag_literal(container.tag_type, tag));
+                let attrs = field_attrs(&variant.fields)?;
+                check_struct_only(&variant.fields, &attrs)?;
+                let (pattern, field_encodes) = match variant.fields {
+                    Fields::Unit => (quote! { Self::#variant_name }, quote! {}),
                     Fields::Unnamed(ref fields) => {
-
-                        let field_names = fields.unnamed.iter().enumerate().map(|(i, _)| {
-                            let field_name = syn::Ident::new(&format!("field_{i}"), proc_macro2::Span::call_site());
-                            quote! {
-                                ref #field_name 
+                        // Skipped fields are matched with `_` so they don't trip unused warnings
+                        let field_names = fields.unnamed.iter().zip(&attrs).enumerate().map(|(i, (_, attrs))| {
+                            if attrs.skip.is_some() {
+                                return quote! { _ };
                             }
-                        });
-
-                        let field_encodes = fields.unnamed.iter().enumerate().map(|(i, _)| {
                             let field_name = syn::Ident::new(&format!("field_{i}"), proc_macro2::Span::call_site());
                             quote! {
-                                ByteEncode::simple_encode(#field_name, bytes)?;
+                                ref #field_name
                             }
                         });
-                        let encode_tokens = quote! {
-                            Self::#variant_name(#(#field_names),*) => {
-                                bytes.push(#idx); 
-                                #(#field_encodes)*
-                                Ok(())
-                            }
-                        };
-
-                        println!("{encode_tokens}");
 
-                        encode_tokens
+                        let field_encodes = fields.unnamed.iter().zip(&attrs).enumerate()
+                            .filter(|(_, (_, attrs))| attrs.skip.is_none())
+                            .map(|(i, (_, attrs))| {
+                                let field_name = syn::Ident::new(&format!("field_{i}"), proc_macro2::Span::call_site());
+                                encode_value(krate, attrs, quote! { #field_name })
+                            });
+                        (quote! { Self::#variant_name(#(#field_names),*) }, quote! { #(#field_encodes)* })
                     },
                     Fields::Named(ref named_fields) => {
-                        let struct_field_names = named_fields.named.iter().map(|field| {
+                        let encoded_fields: Vec<_> = named_fields.named.iter().zip(&attrs)
+                            .filter(|(_, attrs)| attrs.skip.is_none())
+                            .collect();
+
+                        let struct_field_names = encoded_fields.iter().map(|(field, _)| {
                             let ident = &field.ident;
                             quote! {
                                 ref #ident
                             }
                         });
 
-                        let encode_fields = named_fields.named.iter().map(|field| {
+                        let encode_fields = encoded_fields.iter().map(|(field, attrs)| {
                             let ident = &field.ident;
-                            quote! {
-                                ByteEncode::simple_encode(#ident, bytes)?;
-                            }
+                            encode_value(krate, attrs, quote! { #ident })
                         });
 
-                        let encode_tokens = quote! {
-                            Self::#variant_name{#(#struct_field_names),*} => {
-                                bytes.push(#idx); 
-                                #(#encode_fields)*
-                                Ok(())
-                            }
-                        };
-
-                        println!("{encode_tokens}");
-
-                        encode_tokens
+                        (quote! { Self::#variant_name{#(#struct_field_names,)* ..} }, quote! { #(#encode_fields)* })
                     },
-                }
-            });
+                };
+                let body = variant_encode_body(krate, &container, encode_tag, field_encodes.clone());
+                variant_encodes.push(quote! {
+                    #pattern => {
+                        #body
+                    }
+                });
+                untagged_writes |= !field_encodes.is_empty();
+                let untagged_body = variant_encode_body(krate, &container, quote! {}, field_encodes);
+                untagged_encodes.push(quote! {
+                    #pattern => {
+                        #untagged_body
+                    }
+                });
+                variant_tags.push(quote! { Self::#variant_name { .. } => #tag });
+            }
+            let unused_bytes = match untagged_writes {
+                true => quote! {},
+                false => quote! { let _ = #bytes; },
+            };
             quote! {
-                impl ByteEncode for #name {
-                    fn simple_encode(&self, bytes: &mut Vec<u8>) -> Result<(), byte_transport::Error> {
+                impl #krate::ByteEncode for #name {
+                    fn simple_encode(&self, #bytes: &mut ::std::vec::Vec<u8>) -> ::core::result::Result<(), #krate::Error> {
                         match *self {
                             #(#variant_encodes,)*
                         }
                     }
                 }
+
+                impl #krate::EncodeVariant for #name {
+                    fn variant_tag(&self) -> u64 {
+                        match *self {
+                            #(#variant_tags,)*
+                        }
+                    }
+
+                    fn encode_variant(&self, #bytes: &mut ::std::vec::Vec<u8>) -> ::core::result::Result<(), #krate::Error> {
+                        #unused_bytes
+                        match *self {
+                            #(#untagged_encodes,)*
+                        }
+                    }
+                }
             }
         },
-        _ => panic!("ByteEncode can only be derived for structs and enums."),
+        Data::Union(ref data_union) => {
+            return Err(syn::Error::new_spanned(data_union.union_token, "ByteEncode can only be derived for structs and enums"));
+        },
     };
 
-    TokenStream::from(encode_impl)
+    Ok(encode_impl)
 }
 
 // Derive macro for ByteDecode
-#[proc_macro_derive(ByteDecode, attributes(ig))]
+#[proc_macro_derive(ByteDecode, attributes(ig, bt))]
 pub fn derive_byte_decode(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
 
-    let name = &input.ident;
+    expand_byte_decode(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
 
-    let decode_impl = match input.data {
-        Data::Struct(ref data) => {
+fn expand_byte_decode(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let bytes = internal_ident("bytes");
+    let decoder = internal_ident("decoder");
+    let name = &input.ident;
+    let container = ContainerAttrs::from_input(input)?;
+    let krate = &container.krate;
+    let validate = match container.validate {
+        Some(ref path) => quote! { #path(&value)?; },
+        None => quote! {},
+    };
 
-            let ignored_fields = data.fields.iter().filter(|f| {
-                let attrs = &f.attrs;
-                false
-            });
+    let proxy_body = match (&container.from, &container.try_from) {
+        (Some(wire), _) => Some(quote! {
+            let wire = <#wire as #krate::ByteDecode>::simple_decode(#decoder)?;
+            let value: Self = ::core::convert::From::from(wire);
+        }),
+        (None, Some(wire)) => Some(quote! {
+            let wire = <#wire as #krate::ByteDecode>::simple_decode(#decoder)?;
+            let value: Self = ::core::convert::TryFrom::try_from(wire).map_err(|error| #krate::Error::Conversion {
+                type_name: ::core::any::type_name::<Self>(),
+                reason: ::std::string::ToString::to_string(&error),
+            })?;
+        }),
+        (None, None) => None,
+    };
 
-            let field_decodes = data.fields.iter().map(|f| {
-                println!("Fields {:?}", f.to_token_stream());
-                let name = &f.ident;
+    // Enums also implement `DecodeVariant`, for when the tag comes from elsewhere
+    let mut variant_impl = None;
+    let decode_body = match input.data {
+        // #[bt(from)] and #[bt(try_from)] decode the proxy type and convert it
+        _ if proxy_body.is_some() => quote! {
+            #proxy_body
+            #validate
+            ::core::result::Result::Ok(value)
+        },
+        Data::Struct(ref data) if container.c_layout => {
+            check_c_layout_fields(&data.fields)?;
+            let endian = c_layout_endian(krate, &container);
+            quote! {
+                let #bytes = #decoder.read_slice(::core::mem::size_of::<Self>())?;
+                let value = <Self as #krate::CLayout>::read_c(#bytes, #endian)?;
+                #validate
+                ::core::result::Result::Ok(value)
+            }
+        },
+        Data::Struct(ref data) => {
+            let attrs = field_attrs(&data.fields)?;
+            if container.transparent {
+                check_transparent(&data.fields, &attrs)?;
+            }
+            check_default_on_eof(&data.fields, &attrs)?;
+            let bits = presence_bits(&data.fields, &attrs, &container)?;
+            let presence = internal_ident("presence");
+            let presence_mask = match presence_len(&bits) {
+                0 => quote! {},
+                len => quote! { let #presence = #decoder.read_array::<#len>()?; },
+            };
+            let checksum_start = match attrs.iter().any(|attrs| attrs.checksum.is_some()) {
+                true => {
+                    let checksum_start = internal_ident("checksum_start");
+                    quote! { let #checksum_start = #decoder.index; }
+                },
+                false => quote! {},
+            };
+            let field_decodes = data.fields.iter().zip(&attrs).zip(&bits).enumerate().map(|(i, ((f, attrs), bit))| {
+                let name = field_binding(i, f);
                 let field_type = &f.ty;
+                let value = layout_decode(krate, attrs, field_label(i, f))
+                    .or_else(|| external_decode(krate, attrs, field_type))
+                    .unwrap_or_else(|| decode_value(krate, attrs));
+                let value = match *bit {
+                    Some(bit) => {
+                        let (byte, mask) = presence_position(bit);
+                        quote! {
+                            if #presence[#byte] & #mask != 0 {
+                                ::core::option::Option::Some(#value)
+                            } else {
+                                ::core::option::Option::None
+                            }
+                        }
+                    },
+                    None => value,
+                };
+                let check = range_check(krate, attrs, &name, field_label(i, f));
                 quote! {
-                    let #name: #field_type = ByteDecode::simple_decode(decoder)?;
+                    let #name: #field_type = #value;
+                    #check
                 }
             });
-            let field_names = data.fields.iter().map(|f| {
-                let name = &f.ident;
-                quote! { #name }
-            });
-            let stream = quote! {
-                impl ByteDecode for #name {
-                    fn simple_decode(decoder: &mut byte_transport::Decoder) -> Result<Self, byte_transport::Error> {
-                        #(#field_decodes)*
-                        Ok(Self {
-                            #(#field_names),*
-                        })
-                    }
-                }
+            let field_names = data.fields.iter().enumerate().map(|(i, f)| field_binding(i, f));
+            let construct = match data.fields {
+                Fields::Named(_) => quote! { Self { #(#field_names),* } },
+                Fields::Unnamed(_) => quote! { Self(#(#field_names),*) },
+                Fields::Unit => quote! { Self },
             };
-
-            println!("Struct {name} \n{stream}");
-            stream
+            // Nothing reads the decoder when every field is skipped or there are none
+            let unused_decoder = match attrs.iter().all(|attrs| attrs.skip.is_some()) {
+                true => quote! { let _ = #decoder; },
+                false => quote! {},
+            };
+            quote! {
+                #unused_decoder
+                #checksum_start
+                #presence_mask
+                #(#field_decodes)*
+                let value = #construct;
+                #validate
+                ::core::result::Result::Ok(value)
+            }
         },
          // Enum handling
         Data::Enum(ref data_enum) => {
-            let variant_decodes = data_enum.variants.iter().enumerate().map(|(idx, variant)| {
+            let tags = enum_tags(data_enum, &container)?;
+            let mut variant_decodes = Vec::new();
+            let mut unknown_variant = quote! {
+                _ => ::core::result::Result::Err(#krate::Error::DecodingEnumVariant(::core::convert::From::from(variant_idx))),
+            };
+            for (variant, tag) in data_enum.variants.iter().zip(tags) {
                 let variant_name = &variant.ident;
-                let idx = idx as u8;  // Enum variant index as a `u8`
-                match variant.fields {
+                let Some(tag) = tag else {
+                    let (_, construct) = other_variant_parts(variant);
+                    unknown_variant = quote! {
+                        _ => {
+                            let payload = ::core::mem::take(&mut #decoder.bytes);
+                            ::core::result::Result::<Self, #krate::Error>::Ok(#construct)
+                        }
+                    };
+                    continue;
+                };
+                let idx = tag_literal(container.tag_type, tag);
+                let attrs = field_attrs(&variant.fields)?;
+                check_struct_only(&variant.fields, &attrs)?;
+                if let Some((field, _)) = variant.fields.iter().zip(&attrs).find(|(_, attrs)| attrs.default_on_eof) {
+                    return Err(syn::Error::new_spanned(field, "#[bt(default_on_eof)] is only supported on struct fields"));
+                }
+                let stream = match variant.fields {
                     Fields::Unit => quote! {
-                        #idx => Ok(Self::#variant_name),
+                        #idx => ::core::result::Result::Ok(Self::#variant_name),
                     },
                     Fields::Unnamed(ref fields) => {
-                        println!("Fields {:?}", fields.to_token_stream());
-                        let field_decodes = fields.unnamed.iter().enumerate().map(|(i, field)| {
+                        let field_decodes = fields.unnamed.iter().zip(&attrs).enumerate().map(|(i, (field, attrs))| {
                             let field_ident = syn::Ident::new(&format!("field_{i}"), proc_macro2::Span::call_site());
-                            
+                            let value = decode_value(krate, attrs);
+                            let check = range_check(krate, attrs, &field_ident, field_label(i, field));
+
                             quote! {
-                                let #field_ident = ByteDecode::simple_decode(decoder)?;
+                                let #field_ident = #value;
+                                #check
                             }
                         });
 
@@ -174,51 +1051,104 @@ pub fn derive_byte_decode(input: TokenStream) -> TokenStream {
                             syn::Ident::new(&format!("field_{i}"), proc_macro2::Span::call_site())
                         });
 
-                        let stream = quote! {
+                        quote! {
                             #idx => {
                                 #(#field_decodes)*
-                                Ok(Self::#variant_name(#(#field_names),*))
+                                ::core::result::Result::Ok(Self::#variant_name(#(#field_names),*))
                             }
-                        };
-                        println!("Unnamed Fields result: {stream}");
-                        stream
+                        }
                     },
                     Fields::Named(ref named_fields) => {
-                        let field_decodes = named_fields.named.iter().map(|named_field| {
-                            println!("{:?}", named_field.to_token_stream());
-                            let field_ident = &named_field.ident;
-                            let field_type = &named_field.ty;
+                        let field_decodes = named_fields.named.iter().zip(&attrs).enumerate().map(|(i, (named_field, attrs))| {
+                            let field_ident = field_binding(i, named_field);
+                            let value = decode_value(krate, attrs);
+                            let check = range_check(krate, attrs, &field_ident, field_label(i, named_field));
                             quote! {
-                                #field_ident: ByteDecode::simple_decode(decoder)?,
+                                let #field_ident = #value;
+                                #check
                             }
                         });
 
-                        let stream = quote! {
+                        let field_names = named_fields.named.iter().map(|named_field| &named_field.ident);
+
+                        quote! {
                             #idx => {
-                                Ok(Self::#variant_name {
-                                    #(#field_decodes)*
+                                #(#field_decodes)*
+                                ::core::result::Result::Ok(Self::#variant_name {
+                                    #(#field_names),*
                                 })
                             }
-                        };
-
-                        println!("Byte Decode implementation: {stream}");
-                        stream
+                        }
                     },
-                }
+                };
+                variant_decodes.push(stream);
+            }
+            let decode_tag = tag_decode(krate, container.tag_type);
+            // Variants are decoded from their own payload, so unknown tags and trailing
+            // fields added by newer peers are skipped without losing the rest of the input
+            let payload_decoder = match container.length_prefixed {
+                true => {
+                    let (payload_len, payload, payload_decoder) = (internal_ident("payload_len"), internal_ident("payload"), internal_ident("payload_decoder"));
+                    quote! {
+                        let #payload_len = <#krate::VarInt as #krate::ByteDecode>::simple_decode(#decoder)?.0;
+                        let #payload_len = usize::try_from(#payload_len).map_err(|_| #krate::Error::SimpleDecodeTryFrom)?;
+                        let #payload = #decoder.read_slice(#payload_len)?.to_vec();
+                        let mut #payload_decoder = #krate::Decoder::with_options(#payload, #decoder.options);
+                        let #decoder = &mut #payload_decoder;
+                    }
+                },
+                false => quote! {},
+            };
+            // The tag is read here, or taken from a sibling field by #[bt(tag_from)]
+            let tag_integer = tag_integer(container.tag_type);
+            let variant_body = finite_body(krate, &container, quote! {
+                let variant_idx = <#tag_integer as ::core::convert::TryFrom<u64>>::try_from(tag)
+                    .map_err(|_| #krate::Error::DecodingEnumVariant(tag))?;
+                #payload_decoder
+                let value = match variant_idx {
+                    #(#variant_decodes)*
+                    #unknown_variant
+                }?;
+                #validate
+                ::core::result::Result::Ok(value)
             });
-            quote! {
-                impl ByteDecode for #name {
-                    fn simple_decode(decoder: &mut byte_transport::Decoder) -> Result<Self,byte_transport::Error> {
-                        let variant_idx = u8::simple_decode(decoder)?;
-                        match variant_idx {
-                            #(#variant_decodes)*
-                            _ => Err(byte_transport::Error::DecodingEnumVariant(variant_idx)),
-                        }
+            variant_impl = Some(quote! {
+                impl #krate::DecodeVariant for #name {
+                    fn decode_variant(tag: u64, #decoder: &mut #krate::Decoder) -> ::core::result::Result<Self, #krate::Error> {
+                        #variant_body
                     }
                 }
+            });
+            quote! {
+                let variant_idx = #decode_tag;
+                <Self as #krate::DecodeVariant>::decode_variant(::core::convert::From::from(variant_idx), #decoder)
             }
         },
-        _ => panic!("ByteDecode can only be derived for structs and enums."),    };
+        Data::Union(ref data_union) => {
+            return Err(syn::Error::new_spanned(data_union.union_token, "ByteDecode can only be derived for structs and enums"));
+        },
+    };
+
+    // Enums apply #[bt(finite)] in `decode_variant`, which `simple_decode` defers to
+    let decode_body = match variant_impl {
+        Some(_) => quote! { { #decode_body } },
+        None => finite_body(krate, &container, decode_body),
+    };
+
+    Ok(quote! {
+        impl #krate::ByteDecode for #name {
+            fn simple_decode(#decoder: &mut #krate::Decoder) -> ::core::result::Result<Self, #krate::Error> {
+                #decode_body
+            }
+        }
+
+        #variant_impl
+    })
+}
 
-    TokenStream::from(decode_impl)
+// Function-like macro declaring the message enums of each direction of a protocol
+#[proc_macro]
+pub fn protocol(input: TokenStream) -> TokenStream {
+    let protocol = parse_macro_input!(input as protocol::Protocol);
+    protocol::expand(&protocol).into()
 }
@@ -0,0 +1,195 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{braced, parenthesized, parse_quote, Attribute, Ident, LitInt, LitStr, Path, Token, Type};
+
+// protocol! { #[bt(crate = "path")]? direction { Variant(Type) = tag, ... } ... }
+pub struct Protocol {
+    krate: Path,
+    directions: Vec<Direction>,
+}
+
+struct Direction {
+    name: Ident,
+    messages: Vec<ProtocolMessage>,
+}
+
+struct ProtocolMessage {
+    variant: Ident,
+    ty: Type,
+    tag: u16,
+}
+
+impl Parse for ProtocolMessage {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let variant: Ident = input.parse()?;
+        let content;
+        parenthesized!(content in input);
+        let ty: Type = content.parse()?;
+        input.parse::<Token![=]>()?;
+        let tag: LitInt = input.parse()?;
+        Ok(ProtocolMessage { variant, ty, tag: tag.base10_parse()? })
+    }
+}
+
+impl Parse for Protocol {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut krate: Path = parse_quote! { ::byte_transport };
+        for attr in input.call(Attribute::parse_outer)? {
+            if !attr.path().is_ident("bt") {
+                return Err(syn::Error::new_spanned(attr, "only #[bt(crate = \"path\")] is supported on protocol!"));
+            }
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("crate") {
+                    krate = meta.value()?.parse::<LitStr>()?.parse()?;
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported protocol! attribute"))
+                }
+            })?;
+        }
+
+        let mut directions: Vec<Direction> = Vec::new();
+        while !input.is_empty() {
+            let name: Ident = input.parse()?;
+            if directions.iter().any(|direction| direction.name == name) {
+                return Err(syn::Error::new_spanned(name, "duplicate protocol direction"));
+            }
+            let content;
+            braced!(content in input);
+            let messages: Vec<ProtocolMessage> = Punctuated::<ProtocolMessage, Token![,]>::parse_terminated(&content)?
+                .into_iter()
+                .collect();
+            for (i, message) in messages.iter().enumerate() {
+                if messages[..i].iter().any(|earlier| earlier.tag == message.tag) {
+                    return Err(syn::Error::new_spanned(&message.variant, format!("duplicate message tag {} in {}", message.tag, name)));
+                }
+            }
+            directions.push(Direction { name, messages });
+        }
+        if directions.is_empty() {
+            return Err(input.error("protocol! needs at least one direction"));
+        }
+
+        Ok(Protocol { krate, directions })
+    }
+}
+
+// client_to_server -> ClientToServer
+fn upper_camel(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+// ChatMessage -> chat_message
+fn snake(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            snake.push('_');
+        }
+        snake.extend(c.to_lowercase());
+    }
+    snake
+}
+
+// 64-bit FNV-1a, stable across compiler versions unlike `DefaultHasher`
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
+// Canonical text of a direction, the input to the fingerprints
+fn describe(direction: &Direction) -> String {
+    let messages: Vec<String> = direction.messages.iter()
+        .map(|message| {
+            let ty = &message.ty;
+            format!("{}({})={}", message.variant, quote! { #ty }, message.tag)
+        })
+        .collect();
+    format!("{}{{{}}}", direction.name, messages.join(","))
+}
+
+pub fn expand(protocol: &Protocol) -> TokenStream2 {
+    let krate = &protocol.krate;
+    let descriptions: Vec<String> = protocol.directions.iter().map(describe).collect();
+    let protocol_fingerprint = fnv1a(descriptions.join(";").as_bytes());
+
+    let directions = protocol.directions.iter().zip(&descriptions).map(|(direction, description)| {
+        let enum_name = format_ident!("{}", upper_camel(&direction.name.to_string()));
+        let handler_name = format_ident!("{}Handler", enum_name);
+        let fingerprint = fnv1a(description.as_bytes());
+        let variants: Vec<&Ident> = direction.messages.iter().map(|message| &message.variant).collect();
+        let types: Vec<&Type> = direction.messages.iter().map(|message| &message.ty).collect();
+        let tags: Vec<u16> = direction.messages.iter().map(|message| message.tag).collect();
+        let methods: Vec<Ident> = variants.iter().map(|variant| format_ident!("on_{}", snake(&variant.to_string()))).collect();
+        let enum_doc = format!("Messages sent {}", direction.name.to_string().replace('_', " "));
+        let handler_doc = format!("Receives every `{enum_name}` message through `{enum_name}::dispatch`");
+
+        quote! {
+            #[doc = #enum_doc]
+            #[derive(Debug)]
+            pub enum #enum_name {
+                #(#variants(#types),)*
+            }
+
+            impl #enum_name {
+                /// Fingerprint of this direction's messages and tags
+                pub const FINGERPRINT: u64 = #fingerprint;
+
+                /// Tag written in front of the message
+                pub fn tag(&self) -> u16 {
+                    match *self {
+                        #(Self::#variants(_) => #tags,)*
+                    }
+                }
+
+                /// Calls the handler method for this message
+                pub fn dispatch<H: #handler_name + ?::core::marker::Sized>(self, handler: &mut H) {
+                    match self {
+                        #(Self::#variants(message) => handler.#methods(message),)*
+                    }
+                }
+            }
+
+            #[doc = #handler_doc]
+            pub trait #handler_name {
+                #(fn #methods(&mut self, message: #types);)*
+            }
+
+            impl #krate::ByteEncode for #enum_name {
+                fn simple_encode(&self, bytes: &mut ::std::vec::Vec<u8>) -> ::core::result::Result<(), #krate::Error> {
+                    #krate::ByteEncode::simple_encode(&self.tag(), bytes)?;
+                    match *self {
+                        #(Self::#variants(ref message) => #krate::ByteEncode::simple_encode(message, bytes),)*
+                    }
+                }
+            }
+
+            impl #krate::ByteDecode for #enum_name {
+                fn simple_decode(decoder: &mut #krate::Decoder) -> ::core::result::Result<Self, #krate::Error> {
+                    let tag = <u16 as #krate::ByteDecode>::simple_decode(decoder)?;
+                    match tag {
+                        #(#tags => ::core::result::Result::Ok(Self::#variants(#krate::ByteDecode::simple_decode(decoder)?)),)*
+                        _ => ::core::result::Result::Err(#krate::Error::DecodingEnumVariant(::core::convert::From::from(tag))),
+                    }
+                }
+            }
+        }
+    });
+
+    quote! {
+        /// Fingerprint of every direction of the protocol, to compare when peers connect
+        pub const PROTOCOL_FINGERPRINT: u64 = #protocol_fingerprint;
+
+        #(#directions)*
+    }
+}